@@ -0,0 +1,312 @@
+pub const USAGE: &str = "\
+Usage:
+  lrn2rust-httpserver [OPTIONS]               Run the config file's routes, or else the demo server (default port: 8080)
+  lrn2rust-httpserver serve [DIR] [OPTIONS]   Serve the files in DIR, up to 64 MiB each (default: the current directory, port: 8000)
+  lrn2rust-httpserver --help                  Show this message
+
+Options (each can also be set by its environment variable, which the option overrides,
+and both override the config file):
+  --config FILE            HTTPSERVER_CONFIG            TOML file to configure listeners, routes, limits, timeouts and logging
+  --bind ADDR              HTTPSERVER_BIND              Address to listen on (default: 0.0.0.0)
+  --port N                 HTTPSERVER_PORT              Port to listen on, or 0 for any free port
+  --workers N              HTTPSERVER_WORKERS           How many connections to handle at once (default: 8)
+  --read-timeout SECS      HTTPSERVER_READ_TIMEOUT      How long a request may take to arrive (default: 30)
+  --idle-timeout SECS      HTTPSERVER_IDLE_TIMEOUT      How long a connection may wait between requests (default: 5)
+  --shutdown-timeout SECS  HTTPSERVER_SHUTDOWN_TIMEOUT  How long requests get to finish when stopping (default: 10)
+  --log-level LEVEL        HTTPSERVER_LOG_LEVEL         One of off, error, warn, info, debug or trace (default: info)
+";
+
+pub enum Command {
+    Demo,
+    Serve {
+        dir: std::path::PathBuf,
+    },
+    Help,
+}
+
+/// Settings that apply whatever the command, left as `None` when neither an option nor the environment sets them.
+#[derive(Default)]
+pub struct Options {
+    pub config: Option<std::path::PathBuf>,
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    pub workers: Option<usize>,
+    pub read_timeout: Option<std::time::Duration>,
+    pub idle_timeout: Option<std::time::Duration>,
+    pub shutdown_timeout: Option<std::time::Duration>,
+    pub log_level: Option<log::LevelFilter>,
+}
+
+pub struct Args {
+    pub command: Command,
+    pub options: Options,
+}
+
+/// The timeouts that can be set, as options or in the config file.
+#[derive(Clone, Copy)]
+pub enum Timeout {
+    Read,
+    Idle,
+    Shutdown,
+}
+
+impl Timeout {
+    pub fn allows_zero(self) -> bool {
+        // Sockets can't have a zero read timeout, but stopping can be immediate.
+        matches!(self, Timeout::Shutdown)
+    }
+
+    /// The timeout for a number of seconds, or `None` if it's negative, too large, or zero when that isn't allowed.
+    pub fn duration_for_secs(self, secs: f64) -> Option<std::time::Duration> {
+        std::time::Duration::try_from_secs_f64(secs).ok().filter(|timeout| self.allows_zero() || !timeout.is_zero())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Setting {
+    Config,
+    Bind,
+    Port,
+    Workers,
+    ReadTimeout,
+    IdleTimeout,
+    ShutdownTimeout,
+    LogLevel,
+}
+
+impl Setting {
+    const ALL: [Setting; 8] = [Setting::Config, Setting::Bind, Setting::Port, Setting::Workers, Setting::ReadTimeout, Setting::IdleTimeout, Setting::ShutdownTimeout, Setting::LogLevel];
+
+    fn flag(self) -> &'static str {
+        match self {
+            Setting::Config => "--config",
+            Setting::Bind => "--bind",
+            Setting::Port => "--port",
+            Setting::Workers => "--workers",
+            Setting::ReadTimeout => "--read-timeout",
+            Setting::IdleTimeout => "--idle-timeout",
+            Setting::ShutdownTimeout => "--shutdown-timeout",
+            Setting::LogLevel => "--log-level",
+        }
+    }
+
+    fn env_var(self) -> &'static str {
+        match self {
+            Setting::Config => "HTTPSERVER_CONFIG",
+            Setting::Bind => "HTTPSERVER_BIND",
+            Setting::Port => "HTTPSERVER_PORT",
+            Setting::Workers => "HTTPSERVER_WORKERS",
+            Setting::ReadTimeout => "HTTPSERVER_READ_TIMEOUT",
+            Setting::IdleTimeout => "HTTPSERVER_IDLE_TIMEOUT",
+            Setting::ShutdownTimeout => "HTTPSERVER_SHUTDOWN_TIMEOUT",
+            Setting::LogLevel => "HTTPSERVER_LOG_LEVEL",
+        }
+    }
+
+    fn from_flag(flag: &str) -> Option<Setting> {
+        // A few settings also have a short flag, for convenience.
+        match flag {
+            "-c" => Some(Setting::Config),
+            "-b" => Some(Setting::Bind),
+            "-p" => Some(Setting::Port),
+            "-w" => Some(Setting::Workers),
+            _ => Setting::ALL.into_iter().find(|setting| setting.flag() == flag),
+        }
+    }
+
+    fn is_set(self, options: &Options) -> bool {
+        match self {
+            Setting::Config => options.config.is_some(),
+            Setting::Bind => options.bind.is_some(),
+            Setting::Port => options.port.is_some(),
+            Setting::Workers => options.workers.is_some(),
+            Setting::ReadTimeout => options.read_timeout.is_some(),
+            Setting::IdleTimeout => options.idle_timeout.is_some(),
+            Setting::ShutdownTimeout => options.shutdown_timeout.is_some(),
+            Setting::LogLevel => options.log_level.is_some(),
+        }
+    }
+
+    /// Parse a value for this setting into the options; `source` names where it came from, for the error if it's invalid.
+    fn apply(self, options: &mut Options, value: &str, source: &str) -> Result<(), String> {
+        let invalid_value = || format!("Invalid value {:?} for {}", value, source);
+
+        match self {
+            Setting::Config => {
+                if value.is_empty() {
+                    return Err(invalid_value());
+                }
+                options.config = Some(std::path::PathBuf::from(value));
+            },
+            Setting::Bind => {
+                if value.is_empty() {
+                    return Err(invalid_value());
+                }
+                options.bind = Some(value.to_string());
+            },
+            Setting::Port => {
+                options.port = Some(value.parse().map_err(|_| invalid_value())?);
+            },
+            Setting::Workers => {
+                let workers: usize = value.parse().map_err(|_| invalid_value())?;
+                if workers == 0 {
+                    return Err(invalid_value());
+                }
+                options.workers = Some(workers);
+            },
+            Setting::ReadTimeout => {
+                options.read_timeout = Some(parse_timeout(Timeout::Read, value).ok_or_else(invalid_value)?);
+            },
+            Setting::IdleTimeout => {
+                options.idle_timeout = Some(parse_timeout(Timeout::Idle, value).ok_or_else(invalid_value)?);
+            },
+            Setting::ShutdownTimeout => {
+                options.shutdown_timeout = Some(parse_timeout(Timeout::Shutdown, value).ok_or_else(invalid_value)?);
+            },
+            Setting::LogLevel => {
+                options.log_level = Some(value.parse().map_err(|_| invalid_value())?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_timeout(timeout: Timeout, value: &str) -> Option<std::time::Duration> {
+    timeout.duration_for_secs(value.parse().ok()?)
+}
+
+/// Parse the command line's arguments (not including the program name), filling in any options they don't set
+/// from the environment variables that `env_var` looks up.
+pub fn parse_args(mut args: impl Iterator<Item = String>, env_var: impl Fn(&str) -> Option<String>) -> Result<Args, String> {
+    let mut positional_args = Vec::new();
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Args {
+                command: Command::Help,
+                options,
+            });
+        }
+
+        if !arg.starts_with('-') || arg == "-" {
+            positional_args.push(arg);
+            continue;
+        }
+
+        // Options take their value from either the next argument, or after an '=', e.g. "--port=8080".
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let Some(setting) = Setting::from_flag(flag) else {
+            return Err(format!("Unrecognized option {}", flag));
+        };
+        let Some(value) = inline_value.or_else(|| args.next()) else {
+            return Err(format!("Missing value for {}", flag));
+        };
+        setting.apply(&mut options, &value, flag)?;
+    }
+
+    for setting in Setting::ALL {
+        if setting.is_set(&options) {
+            continue;
+        }
+        if let Some(value) = env_var(setting.env_var()) {
+            setting.apply(&mut options, &value, setting.env_var())?;
+        }
+    }
+
+    let mut positional_args = positional_args.into_iter();
+    let command = match positional_args.next().as_deref() {
+        None => Command::Demo,
+        Some("help") => Command::Help,
+        Some("serve") => Command::Serve {
+            dir: std::path::PathBuf::from(positional_args.next().unwrap_or_else(|| ".".to_string())),
+        },
+        Some(unknown_arg) => return Err(format!("Unrecognized command {}", unknown_arg)),
+    };
+    if let Some(unexpected_arg) = positional_args.next() {
+        return Err(format!("Unexpected argument {}", unexpected_arg));
+    }
+
+    Ok(Args {
+        command,
+        options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    fn parse(args: &[&str], env_vars: &[(&str, &str)]) -> Result<super::Args, String> {
+        let env_var = |name: &str| env_vars.iter().find(|(env_name, _)| *env_name == name).map(|(_, value)| value.to_string());
+        super::parse_args(args.iter().map(|arg| arg.to_string()), env_var)
+    }
+
+    fn parse_ok(args: &[&str], env_vars: &[(&str, &str)]) -> super::Args {
+        match parse(args, env_vars) {
+            Ok(parsed_args) => parsed_args,
+            Err(message) => panic!("{:?} failed to parse: {}", args, message),
+        }
+    }
+
+    #[test]
+    fn options_override_env_vars() {
+        let env_vars = [("HTTPSERVER_PORT", "9000"), ("HTTPSERVER_WORKERS", "2"), ("HTTPSERVER_LOG_LEVEL", "debug")];
+        let options = parse_ok(&["--port", "8080"], &env_vars).options;
+        assert_eq!(options.port, Some(8080));
+        assert_eq!(options.workers, Some(2));
+        assert_eq!(options.log_level, Some(log::LevelFilter::Debug));
+        assert_eq!(options.bind, None);
+
+        // An env var is only checked when the option doesn't set it, so a bad one can be overridden.
+        assert_eq!(parse_ok(&["--workers", "4"], &[("HTTPSERVER_WORKERS", "lots")]).options.workers, Some(4));
+        assert_eq!(parse(&[], &[("HTTPSERVER_WORKERS", "lots")]).err(), Some("Invalid value \"lots\" for HTTPSERVER_WORKERS".to_string()));
+    }
+
+    #[test]
+    fn accepts_inline_values_and_short_flags() {
+        let options = parse_ok(&["--bind=127.0.0.1", "-p", "80", "-w", "3", "-c", "site.toml", "--read-timeout=1.5", "-b", "::1"], &[]).options;
+        assert_eq!(options.bind.as_deref(), Some("::1"));
+        assert_eq!(options.port, Some(80));
+        assert_eq!(options.workers, Some(3));
+        assert_eq!(options.config, Some(std::path::PathBuf::from("site.toml")));
+        assert_eq!(options.read_timeout, Some(std::time::Duration::from_millis(1500)));
+
+        // Only the first '=' splits, so a value can contain more.
+        assert_eq!(parse_ok(&["--config=a=b.toml"], &[]).options.config, Some(std::path::PathBuf::from("a=b.toml")));
+        assert_eq!(parse(&["--port"], &[]).err(), Some("Missing value for --port".to_string()));
+        assert_eq!(parse(&["-p="], &[]).err(), Some("Invalid value \"\" for -p".to_string()));
+    }
+
+    #[test]
+    fn rejects_zero_workers_and_timeouts() {
+        assert_eq!(parse(&["--workers", "0"], &[]).err(), Some("Invalid value \"0\" for --workers".to_string()));
+        assert_eq!(parse(&["--read-timeout", "0"], &[]).err(), Some("Invalid value \"0\" for --read-timeout".to_string()));
+        assert_eq!(parse(&[], &[("HTTPSERVER_IDLE_TIMEOUT", "0.0")]).err(), Some("Invalid value \"0.0\" for HTTPSERVER_IDLE_TIMEOUT".to_string()));
+        assert_eq!(parse(&["--idle-timeout", "-1"], &[]).err(), Some("Invalid value \"-1\" for --idle-timeout".to_string()));
+        assert_eq!(parse_ok(&["--shutdown-timeout", "0"], &[]).options.shutdown_timeout, Some(std::time::Duration::ZERO));
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse_ok(&[], &[]).command, super::Command::Demo));
+        assert!(matches!(parse_ok(&["help"], &[]).command, super::Command::Help));
+        assert!(matches!(parse_ok(&["serve", "--port", "1", "--help"], &[]).command, super::Command::Help));
+
+        let super::Command::Serve { dir } = parse_ok(&["serve"], &[]).command else { panic!("expected serve") };
+        assert_eq!(dir, std::path::PathBuf::from("."));
+        let super::Command::Serve { dir } = parse_ok(&["serve", "-p", "80", "public"], &[]).command else { panic!("expected serve") };
+        assert_eq!(dir, std::path::PathBuf::from("public"));
+    }
+
+    #[test]
+    fn rejects_unknown_and_extra_args() {
+        assert_eq!(parse(&["--verbose"], &[]).err(), Some("Unrecognized option --verbose".to_string()));
+        assert_eq!(parse(&["--verbose=1"], &[]).err(), Some("Unrecognized option --verbose".to_string()));
+        assert_eq!(parse(&["start"], &[]).err(), Some("Unrecognized command start".to_string()));
+        assert_eq!(parse(&["serve", "a", "b"], &[]).err(), Some("Unexpected argument b".to_string()));
+        assert_eq!(parse(&["help", "me"], &[]).err(), Some("Unexpected argument me".to_string()));
+    }
+}
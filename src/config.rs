@@ -0,0 +1,419 @@
+// The config file's layout, as written; ServerConfig::parse() checks it over before anything uses it.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    workers: Option<toml::Spanned<usize>>,
+    queue_depth: Option<usize>,
+    #[serde(default)]
+    listeners: Vec<ListenerSection>,
+    #[serde(default)]
+    routes: Vec<RouteSection>,
+    #[serde(default)]
+    limits: LimitsSection,
+    #[serde(default)]
+    timeouts: TimeoutsSection,
+    #[serde(default)]
+    logging: LoggingSection,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListenerSection {
+    bind: Option<String>,
+    port: u16,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteSection {
+    path: toml::Spanned<String>,
+    #[serde(rename = "static")]
+    static_dir: Option<toml::Spanned<String>>,
+    listing: Option<toml::Spanned<bool>>,
+    index_file: Option<toml::Spanned<String>>,
+    proxy: Option<toml::Spanned<String>>,
+    redirect: Option<toml::Spanned<String>>,
+    status: Option<toml::Spanned<u16>>,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsSection {
+    max_uri_len: Option<toml::Spanned<usize>>,
+    max_header_count: Option<toml::Spanned<usize>>,
+    max_header_line_len: Option<toml::Spanned<usize>>,
+    max_headers_len: Option<toml::Spanned<usize>>,
+    max_body_len: Option<toml::Spanned<usize>>,
+    max_requests_per_connection: Option<toml::Spanned<usize>>,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeoutsSection {
+    read: Option<toml::Spanned<f64>>,
+    idle: Option<toml::Spanned<f64>>,
+    shutdown: Option<toml::Spanned<f64>>,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoggingSection {
+    level: Option<toml::Spanned<String>>,
+}
+
+/// A problem with a config file, and the line it's on (when it's about one line in particular).
+#[derive(Debug)]
+pub struct ConfigError {
+    path: std::path::PathBuf,
+    line: Option<usize>,
+    message: String,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub struct ListenerConfig {
+    pub bind: String,
+    pub port: u16,
+}
+
+pub enum RouteTarget {
+    Static {
+        dir: std::path::PathBuf,
+        listing: bool,
+        index_file: Option<String>,
+    },
+    Proxy(lrn2rust_httpserver::Proxy),
+    Redirect {
+        location: String,
+        status: http::StatusCode,
+    },
+}
+
+pub struct RouteConfig {
+    pub path: String,
+    pub target: RouteTarget,
+}
+
+/// Everything a config file can set up, already checked for mistakes.
+/// Settings the file leaves out are `None` (or empty), so the server's own defaults apply.
+#[derive(Default)]
+pub struct ServerConfig {
+    pub listeners: Vec<ListenerConfig>,
+    pub routes: Vec<RouteConfig>,
+    pub parser_limits: lrn2rust_httpserver::ParserLimits,
+    pub max_requests_per_connection: Option<usize>,
+    pub workers: Option<usize>,
+    pub queue_depth: Option<usize>,
+    pub read_timeout: Option<std::time::Duration>,
+    pub idle_timeout: Option<std::time::Duration>,
+    pub shutdown_timeout: Option<std::time::Duration>,
+    pub log_level: Option<log::LevelFilter>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &std::path::Path) -> Result<ServerConfig, ConfigError> {
+        let source = std::fs::read_to_string(path).map_err(|read_error| ConfigError {
+            path: path.to_path_buf(),
+            line: None,
+            message: format!("Couldn't read config file: {}", read_error),
+        })?;
+
+        ServerConfig::parse(&source, path)
+    }
+
+    /// Parse a config file's contents; relative static directories are relative to the file's own directory.
+    pub fn parse(source: &str, path: &std::path::Path) -> Result<ServerConfig, ConfigError> {
+        let config_error = |span: Option<std::ops::Range<usize>>, message: String| ConfigError {
+            path: path.to_path_buf(),
+            line: span.map(|span| line_at(source, span.start)),
+            message,
+        };
+
+        let config_file: ConfigFile = toml::from_str(source).map_err(|toml_error| config_error(toml_error.span(), toml_error.message().to_string()))?;
+        let config_dir = path.parent().unwrap_or(std::path::Path::new("."));
+
+        let mut server_config = ServerConfig {
+            queue_depth: config_file.queue_depth,
+            ..ServerConfig::default()
+        };
+
+        if let Some(workers) = config_file.workers {
+            if *workers.get_ref() == 0 {
+                return Err(config_error(Some(workers.span()), "workers must be at least 1".to_string()));
+            }
+            server_config.workers = Some(workers.into_inner());
+        }
+
+        for listener_section in config_file.listeners {
+            server_config.listeners.push(ListenerConfig {
+                bind: listener_section.bind.unwrap_or_else(|| "0.0.0.0".to_string()),
+                port: listener_section.port,
+            });
+        }
+
+        for route_section in config_file.routes {
+            server_config.routes.push(parse_route(route_section, config_dir).map_err(|(span, message)| config_error(Some(span), message))?);
+        }
+
+        // Limits of zero would reject every request, so they're surely mistakes.
+        let limits = config_file.limits;
+        let limit_fields = [
+            (limits.max_uri_len, "max_uri_len", &mut server_config.parser_limits.max_uri_len),
+            (limits.max_header_count, "max_header_count", &mut server_config.parser_limits.max_header_count),
+            (limits.max_header_line_len, "max_header_line_len", &mut server_config.parser_limits.max_header_line_len),
+            (limits.max_headers_len, "max_headers_len", &mut server_config.parser_limits.max_headers_len),
+            (limits.max_body_len, "max_body_len", &mut server_config.parser_limits.max_body_len),
+        ];
+        for (limit, limit_name, parser_limit) in limit_fields {
+            if let Some(limit) = limit {
+                *parser_limit = positive_limit(limit, limit_name).map_err(|(span, message)| config_error(Some(span), message))?;
+            }
+        }
+        if let Some(max_requests) = limits.max_requests_per_connection {
+            server_config.max_requests_per_connection = Some(positive_limit(max_requests, "max_requests_per_connection").map_err(|(span, message)| config_error(Some(span), message))?);
+        }
+
+        let timeouts = config_file.timeouts;
+        let timeout_fields = [
+            (timeouts.read, "read", crate::cli::Timeout::Read, &mut server_config.read_timeout),
+            (timeouts.idle, "idle", crate::cli::Timeout::Idle, &mut server_config.idle_timeout),
+            (timeouts.shutdown, "shutdown", crate::cli::Timeout::Shutdown, &mut server_config.shutdown_timeout),
+        ];
+        for (timeout_secs, timeout_name, timeout_kind, server_timeout) in timeout_fields {
+            if let Some(timeout_secs) = timeout_secs {
+                match timeout_kind.duration_for_secs(*timeout_secs.get_ref()) {
+                    Some(timeout) => *server_timeout = Some(timeout),
+                    None => {
+                        let min_secs = if timeout_kind.allows_zero() { "at least 0" } else { "more than 0" };
+                        return Err(config_error(Some(timeout_secs.span()), format!("{} timeout must be a number of seconds, {}", timeout_name, min_secs)));
+                    }
+                }
+            }
+        }
+
+        if let Some(log_level) = config_file.logging.level {
+            match log_level.get_ref().parse() {
+                Ok(level_filter) => server_config.log_level = Some(level_filter),
+                Err(_) => return Err(config_error(Some(log_level.span()), format!("Unrecognized log level {:?}, expected one of off, error, warn, info, debug or trace", log_level.get_ref()))),
+            }
+        }
+
+        Ok(server_config)
+    }
+
+    /// A router for the config file's routes, in the order they're listed.
+    pub fn router(&mut self) -> lrn2rust_httpserver::Router {
+        let mut router = lrn2rust_httpserver::Router::new();
+        for route in self.routes.drain(..) {
+            router = match route.target {
+                RouteTarget::Static { dir, listing, index_file } => {
+                    let mut static_files = lrn2rust_httpserver::StaticFiles::new(dir).listing(listing);
+                    if let Some(index_file) = index_file {
+                        static_files = static_files.index_file(&index_file);
+                    }
+                    router.get(&route.path, static_files)
+                },
+                RouteTarget::Proxy(proxy) => router.any(&route.path, proxy),
+                RouteTarget::Redirect { location, status } => router.any(&route.path, lrn2rust_httpserver::Redirect::to(&location).status(status)),
+            };
+        }
+        router
+    }
+}
+
+type SpannedError = (std::ops::Range<usize>, String);
+
+fn parse_route(route_section: RouteSection, config_dir: &std::path::Path) -> Result<RouteConfig, SpannedError> {
+    let path_span = route_section.path.span();
+    let path = route_section.path.into_inner();
+
+    // The router would panic on a misplaced wildcard, so that's caught here instead.
+    if !path.starts_with('/') {
+        return Err((path_span, format!("Route path {} must start with '/'", path)));
+    }
+    let path_segments: Vec<&str> = path.split('/').collect();
+    if path_segments.iter().rev().skip(1).any(|path_segment| path_segment.starts_with('*')) {
+        return Err((path_span, format!("Route path {} can only have a wildcard as its last segment", path)));
+    }
+    let ends_with_wildcard = path_segments.last().is_some_and(|path_segment| path_segment.starts_with('*'));
+
+    let target_count = [route_section.static_dir.is_some(), route_section.proxy.is_some(), route_section.redirect.is_some()].iter().filter(|has_target| **has_target).count();
+    if target_count != 1 {
+        return Err((path_span, format!("Route {} needs exactly one of static, proxy or redirect", path)));
+    }
+
+    if route_section.static_dir.is_none() {
+        if let Some(listing) = &route_section.listing {
+            return Err((listing.span(), "listing only applies to static routes".to_string()));
+        }
+        if let Some(index_file) = &route_section.index_file {
+            return Err((index_file.span(), "index_file only applies to static routes".to_string()));
+        }
+    }
+    if route_section.redirect.is_none() && let Some(status) = &route_section.status {
+        return Err((status.span(), "status only applies to redirect routes".to_string()));
+    }
+
+    let target = if let Some(static_dir) = route_section.static_dir {
+        if !ends_with_wildcard {
+            return Err((path_span, format!("Static route path {} must end with a wildcard, e.g. \"/assets/*path\"", path)));
+        }
+
+        let dir = config_dir.join(static_dir.get_ref());
+        if !dir.is_dir() {
+            return Err((static_dir.span(), format!("Static directory {} doesn't exist", dir.display())));
+        }
+
+        RouteTarget::Static {
+            dir,
+            listing: route_section.listing.is_some_and(|listing| listing.into_inner()),
+            index_file: route_section.index_file.map(|index_file| index_file.into_inner()),
+        }
+    } else if let Some(proxy) = route_section.proxy {
+        match lrn2rust_httpserver::Proxy::new(proxy.get_ref()) {
+            Ok(upstream_proxy) => RouteTarget::Proxy(upstream_proxy),
+            Err(proxy_error) => return Err((proxy.span(), proxy_error.to_string())),
+        }
+    } else if let Some(redirect) = route_section.redirect {
+        if http::HeaderValue::from_str(redirect.get_ref()).is_err() || redirect.get_ref().is_empty() {
+            return Err((redirect.span(), format!("Invalid redirect location {:?}", redirect.get_ref())));
+        }
+
+        let status = match route_section.status {
+            Some(status) => match http::StatusCode::from_u16(*status.get_ref()) {
+                Ok(redirect_status) if redirect_status.is_redirection() => redirect_status,
+                _ => return Err((status.span(), format!("Redirect status {} must be a 3xx status", status.get_ref()))),
+            },
+            None => http::StatusCode::FOUND,
+        };
+
+        RouteTarget::Redirect {
+            location: redirect.into_inner(),
+            status,
+        }
+    } else {
+        unreachable!("Route has exactly one target");
+    };
+
+    Ok(RouteConfig {
+        path,
+        target,
+    })
+}
+
+fn positive_limit(limit: toml::Spanned<usize>, limit_name: &str) -> Result<usize, SpannedError> {
+    match limit.get_ref() {
+        0 => Err((limit.span(), format!("{} must be at least 1", limit_name))),
+        _ => Ok(limit.into_inner()),
+    }
+}
+
+fn line_at(source: &str, offset: usize) -> usize {
+    let offset = std::cmp::min(offset, source.len());
+    source.as_bytes()[..offset].iter().filter(|source_byte| **source_byte == b'\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    fn parse(source: &str) -> Result<super::ServerConfig, super::ConfigError> {
+        // Static directories are relative to the config file, so this one can serve the crate's own "src".
+        let config_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test.toml");
+        super::ServerConfig::parse(source, &config_path)
+    }
+
+    fn error_line(source: &str) -> (Option<usize>, String) {
+        match parse(source) {
+            Ok(_) => panic!("config parsed, but should have failed"),
+            Err(config_error) => (config_error.line, config_error.message),
+        }
+    }
+
+    #[test]
+    fn parses_every_section() {
+        let server_config = parse(r#"
+workers = 4
+queue_depth = 16
+
+[[listeners]]
+port = 8080
+
+[[listeners]]
+bind = "127.0.0.1"
+port = 8081
+
+[[routes]]
+path = "/assets/*path"
+static = "src"
+listing = true
+
+[[routes]]
+path = "/old"
+redirect = "/new"
+status = 308
+
+[[routes]]
+path = "/api/*rest"
+proxy = "http://127.0.0.1:9000"
+
+[limits]
+max_body_len = 1024
+max_requests_per_connection = 10
+
+[timeouts]
+read = 2.5
+shutdown = 0
+
+[logging]
+level = "debug"
+"#).unwrap();
+
+        assert_eq!(server_config.workers, Some(4));
+        assert_eq!(server_config.queue_depth, Some(16));
+        assert_eq!(server_config.listeners.len(), 2);
+        assert_eq!((server_config.listeners[0].bind.as_str(), server_config.listeners[0].port), ("0.0.0.0", 8080));
+        assert_eq!((server_config.listeners[1].bind.as_str(), server_config.listeners[1].port), ("127.0.0.1", 8081));
+        assert_eq!(server_config.routes.len(), 3);
+        assert!(matches!(&server_config.routes[0].target, super::RouteTarget::Static { listing: true, .. }));
+        assert!(matches!(&server_config.routes[1].target, super::RouteTarget::Redirect { status, .. } if *status == http::StatusCode::PERMANENT_REDIRECT));
+        assert!(matches!(&server_config.routes[2].target, super::RouteTarget::Proxy(_)));
+        assert_eq!(server_config.parser_limits.max_body_len, 1024);
+        assert_eq!(server_config.max_requests_per_connection, Some(10));
+        assert_eq!(server_config.read_timeout, Some(std::time::Duration::from_millis(2500)));
+        assert_eq!(server_config.idle_timeout, None);
+        assert_eq!(server_config.shutdown_timeout, Some(std::time::Duration::ZERO));
+        assert_eq!(server_config.log_level, Some(log::LevelFilter::Debug));
+    }
+
+    #[test]
+    fn reports_errors_on_their_lines() {
+        assert_eq!(error_line("workers = 2\nbogus = 1\n").0, Some(2));
+        assert_eq!(error_line("[logging]\nlevel = \"loud\"\n").0, Some(2));
+        assert_eq!(error_line("[limits]\n\nmax_uri_len = 0\n").0, Some(3));
+        assert_eq!(error_line("[timeouts]\nshutdown = 1\nidle = 0\n").0, Some(3));
+        assert_eq!(error_line("workers = \n").0, Some(1));
+        assert_eq!(error_line("[[routes]]\npath = \"/files\"\nstatic = \"src\"\n").0, Some(2));
+        assert_eq!(error_line("[[routes]]\npath = \"/*a/b\"\nredirect = \"/\"\n").0, Some(2));
+        assert_eq!(error_line("[[routes]]\npath = \"/x/*rest\"\nstatic = \"no-such-dir\"\n").0, Some(3));
+        assert_eq!(error_line("[[routes]]\npath = \"/x\"\nredirect = \"/y\"\nstatus = 200\n").0, Some(4));
+        assert_eq!(error_line("[[routes]]\npath = \"/x\"\nproxy = \"https://example.com\"\n").0, Some(3));
+        assert_eq!(error_line("[[routes]]\npath = \"/x\"\nproxy = \"http://example.com\"\nlisting = true\n").0, Some(4));
+    }
+
+    #[test]
+    fn requires_exactly_one_route_target() {
+        let (line, message) = error_line("\n[[routes]]\npath = \"/x\"\nredirect = \"/y\"\nproxy = \"http://example.com\"\n");
+        assert_eq!(line, Some(3));
+        assert_eq!(message, "Route /x needs exactly one of static, proxy or redirect");
+
+        assert_eq!(error_line("[[routes]]\npath = \"/x\"\n").0, Some(2));
+    }
+}
@@ -0,0 +1,346 @@
+struct ListingEntry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<std::time::SystemTime>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+/// A page listing a directory's entries, as HTML or (if the request's `Accept` prefers it) JSON.
+///
+/// Entries are sorted by the `sort` ("name", "size" or "modified") and `order` ("asc" or "desc") query parameters,
+/// with directories always ahead of files.
+pub(crate) fn create_listing_response(request: &crate::Request, dir_path: &std::path::Path, has_parent: bool) -> Result<crate::Response, std::io::Error> {
+    let mut entries = Vec::new();
+    for dir_entry in std::fs::read_dir(dir_path)? {
+        let dir_entry = dir_entry?;
+        // Symlinks are described by what they point to, falling back to the link itself if that's missing.
+        let metadata = match std::fs::metadata(dir_entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => dir_entry.metadata()?,
+        };
+
+        entries.push(ListingEntry {
+            name: dir_entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+
+    let (sort_key, descending) = read_sort_params(request.uri().query());
+    entries.sort_by(|entry_a, entry_b| {
+        let key_ordering = match sort_key {
+            SortKey::Name => entry_a.name.cmp(&entry_b.name),
+            SortKey::Size => entry_a.size.cmp(&entry_b.size).then_with(|| entry_a.name.cmp(&entry_b.name)),
+            SortKey::Modified => entry_a.modified.cmp(&entry_b.modified).then_with(|| entry_a.name.cmp(&entry_b.name)),
+        };
+        let key_ordering = if descending { key_ordering.reverse() } else { key_ordering };
+
+        entry_b.is_dir.cmp(&entry_a.is_dir).then(key_ordering)
+    });
+
+    let accept = request.headers().get(http::header::ACCEPT).and_then(|header_value| header_value.to_str().ok());
+    if prefers_json(accept) {
+        Ok(create_json_listing(request.uri().path(), &entries))
+    } else {
+        Ok(create_html_listing(request.uri().path(), &entries, has_parent, sort_key, descending))
+    }
+}
+
+fn read_sort_params(query: Option<&str>) -> (SortKey, bool) {
+    let mut sort_key = SortKey::Name;
+    let mut descending = false;
+
+    // Unrecognized values are ignored, rather than failing the whole page.
+    let query_params: Vec<(String, String)> = serde_urlencoded::from_str(query.unwrap_or("")).unwrap_or_default();
+    for (param_name, param_value) in query_params {
+        match (param_name.as_str(), param_value.as_str()) {
+            ("sort", "name") => sort_key = SortKey::Name,
+            ("sort", "size") => sort_key = SortKey::Size,
+            ("sort", "modified") => sort_key = SortKey::Modified,
+            ("order", "asc") => descending = false,
+            ("order", "desc") => descending = true,
+            _ => {}
+        }
+    }
+
+    (sort_key, descending)
+}
+
+fn prefers_json(accept: Option<&str>) -> bool {
+    // Without any preference, or with equal ones, the listing is HTML.
+    let Some(accept) = accept else {
+        return false;
+    };
+
+    media_type_quality(accept, "application/json") > media_type_quality(accept, "text/html")
+}
+
+fn media_type_quality(accept: &str, media_type: &str) -> f32 {
+    // The most specific range that matches decides, e.g. "text/html" over "text/*" over "*/*".
+    let type_range = format!("{}/*", media_type.split('/').next().unwrap_or(""));
+
+    let mut best_match: Option<(u8, f32)> = None;
+    for accept_entry in accept.split(',') {
+        let mut entry_parts = accept_entry.split(';');
+        let media_range = entry_parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = entry_parts
+            .filter_map(|entry_param| entry_param.trim().strip_prefix("q="))
+            .find_map(|quality_str| quality_str.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let specificity = if media_range == media_type {
+            3
+        } else if media_range == type_range {
+            2
+        } else if media_range == "*/*" {
+            1
+        } else {
+            continue;
+        };
+
+        if best_match.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+            best_match = Some((specificity, quality));
+        }
+    }
+
+    best_match.map_or(0.0, |(_, quality)| quality)
+}
+
+fn create_json_listing(request_path: &str, entries: &[ListingEntry]) -> crate::Response {
+    let json_entries: Vec<serde_json::Value> = entries.iter().map(|entry| {
+        serde_json::json!({
+            "name": entry.name,
+            "type": if entry.is_dir { "directory" } else { "file" },
+            "size": if entry.is_dir { None } else { Some(entry.size) },
+            "modified": entry.modified.map(format_utc_time),
+        })
+    }).collect();
+
+    let listing_json = serde_json::json!({
+        "path": request_path,
+        "entries": json_entries,
+    });
+    crate::create_bytes_response(http::StatusCode::OK, http::HeaderValue::from_static("application/json"), listing_json.to_string().as_bytes())
+}
+
+fn create_html_listing(request_path: &str, entries: &[ListingEntry], has_parent: bool, sort_key: SortKey, descending: bool) -> crate::Response {
+    let title = format!("Index of {}", escape_html(&crate::router::percent_decode(request_path)));
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n", title));
+    html.push_str("<style>td, th { padding: 0 1em; text-align: left; } td.size { text-align: right; }</style>\n");
+    html.push_str("</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n<table>\n<tr>", title));
+    for (column_key, column_name) in [(SortKey::Name, "Name"), (SortKey::Size, "Size"), (SortKey::Modified, "Modified")] {
+        // Each column sorts ascending, unless it's already doing so, in which case it flips.
+        let column_order = if column_key == sort_key && !descending { "desc" } else { "asc" };
+        let column_param = match column_key {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        };
+        html.push_str(&format!("<th><a href=\"?sort={}&amp;order={}\">{}</a></th>", column_param, column_order, column_name));
+    }
+    html.push_str("</tr>\n");
+
+    if has_parent {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let link_suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir { "-".to_string() } else { format_size(entry.size) };
+        let modified = entry.modified.map(format_utc_time).unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}\">{}{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+            percent_encode(&entry.name), link_suffix, escape_html(&entry.name), link_suffix, size, modified));
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    crate::create_bytes_response(http::StatusCode::OK, http::HeaderValue::from_static("text/html; charset=utf-8"), html.as_bytes())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for text_char in text.chars() {
+        match text_char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(text_char),
+        }
+    }
+    escaped
+}
+
+fn percent_encode(text: &str) -> String {
+    // Everything but the unreserved characters is escaped, so a name can't be mistaken for anything but a path segment.
+    let mut encoded = String::with_capacity(text.len());
+    for text_byte in text.bytes() {
+        if text_byte.is_ascii_alphanumeric() || matches!(text_byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(text_byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", text_byte));
+        }
+    }
+    encoded
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut scaled_size = size as f64;
+    let mut unit_index = 0;
+    while scaled_size >= 1024.0 && unit_index + 1 < UNITS.len() {
+        scaled_size /= 1024.0;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", scaled_size, UNITS[unit_index])
+    }
+}
+
+/// A time as an RFC 3339 UTC timestamp, e.g. "2024-01-31T23:59:59Z".
+fn format_utc_time(time: std::time::SystemTime) -> String {
+    let unix_secs = time.duration_since(std::time::UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+    let (days, day_secs) = ((unix_secs / 86400) as i64, unix_secs % 86400);
+
+    // Converting days to a civil date, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let shifted_days = days + 719468;
+    let era = shifted_days.div_euclid(146097);
+    let day_of_era = shifted_days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, day_secs / 3600, day_secs % 3600 / 60, day_secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    fn make_listed_dir(test_name: &str) -> std::path::PathBuf {
+        let listed_dir = std::env::temp_dir().join(format!("lrn2rust-listing-{}-{}", std::process::id(), test_name));
+        let _ = std::fs::remove_dir_all(&listed_dir);
+        std::fs::create_dir_all(listed_dir.join("zeta")).unwrap();
+        std::fs::create_dir_all(listed_dir.join("alpha")).unwrap();
+        std::fs::write(listed_dir.join("big.txt"), "0123456789").unwrap();
+        std::fs::write(listed_dir.join("a.txt"), "0").unwrap();
+        std::fs::write(listed_dir.join("medium.txt"), "01234").unwrap();
+        listed_dir
+    }
+
+    fn listed_names(listing_response: &crate::Response) -> Vec<String> {
+        let listing_json: serde_json::Value = serde_json::from_slice(listing_response.body()).unwrap();
+        listing_json["entries"].as_array().unwrap().iter().map(|entry| entry["name"].as_str().unwrap().to_string()).collect()
+    }
+
+    fn json_listing(listed_dir: &std::path::Path, uri: &str) -> crate::Response {
+        let request = http::Request::builder().uri(uri).header(http::header::ACCEPT, "application/json").body(Vec::new()).unwrap();
+        super::create_listing_response(&request, listed_dir, false).unwrap()
+    }
+
+    #[test]
+    fn prefers_json_by_quality() {
+        assert!(!super::prefers_json(None));
+        assert!(super::prefers_json(Some("application/json")));
+        assert!(!super::prefers_json(Some("text/html, application/json")));
+        assert!(super::prefers_json(Some("text/html;q=0.5, application/json;q=0.9")));
+        assert!(!super::prefers_json(Some("*/*")));
+        assert!(super::prefers_json(Some("application/json, text/*;q=0.8")));
+        // The most specific range decides, even when a broader one has a higher quality.
+        assert!(super::prefers_json(Some("*/*;q=1.0, text/html;q=0.1, application/json;q=0.5")));
+        assert!(!super::prefers_json(Some("text/*;q=0.9, */*;q=0.8")));
+    }
+
+    #[test]
+    fn matches_most_specific_media_range() {
+        assert_eq!(super::media_type_quality("text/*;q=0.4, */*;q=0.9", "text/html"), 0.4);
+        assert_eq!(super::media_type_quality("TEXT/HTML;q=0.7, text/*;q=0.4", "text/html"), 0.7);
+        assert_eq!(super::media_type_quality("*/*;q=0.2", "application/json"), 0.2);
+        assert_eq!(super::media_type_quality("text/html", "application/json"), 0.0);
+        assert_eq!(super::media_type_quality("application/json;q=oops", "application/json"), 1.0);
+    }
+
+    #[test]
+    fn reads_sort_params() {
+        assert!(super::read_sort_params(None) == (super::SortKey::Name, false));
+        assert!(super::read_sort_params(Some("sort=size&order=desc")) == (super::SortKey::Size, true));
+        assert!(super::read_sort_params(Some("order=desc&sort=modified")) == (super::SortKey::Modified, true));
+        assert!(super::read_sort_params(Some("sort=bogus&order=sideways")) == (super::SortKey::Name, false));
+    }
+
+    #[test]
+    fn sorts_directories_before_files() {
+        let listed_dir = make_listed_dir("sort");
+
+        assert_eq!(listed_names(&json_listing(&listed_dir, "/")), ["alpha", "zeta", "a.txt", "big.txt", "medium.txt"]);
+        assert_eq!(listed_names(&json_listing(&listed_dir, "/?order=desc")), ["zeta", "alpha", "medium.txt", "big.txt", "a.txt"]);
+        assert_eq!(listed_names(&json_listing(&listed_dir, "/?sort=size&order=desc")), ["zeta", "alpha", "big.txt", "medium.txt", "a.txt"]);
+
+        let _ = std::fs::remove_dir_all(&listed_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escapes_hostile_names() {
+        let listed_dir = make_listed_dir("hostile");
+        std::fs::write(listed_dir.join("<img src=x onerror=\"alert('x')\">&.txt"), "").unwrap();
+
+        let request = http::Request::builder().uri("/files/%3Cdir%3E/").body(Vec::new()).unwrap();
+        let listing_response = super::create_listing_response(&request, &listed_dir, true).unwrap();
+        assert_eq!(listing_response.headers()[http::header::CONTENT_TYPE], "text/html; charset=utf-8");
+        let html = crate::test_support::body_text(&listing_response);
+
+        assert!(html.contains("<title>Index of /files/&lt;dir&gt;/</title>"));
+        assert!(html.contains("<a href=\"%3Cimg%20src%3Dx%20onerror%3D%22alert%28%27x%27%29%22%3E%26.txt\">&lt;img src=x onerror=&quot;alert(&#39;x&#39;)&quot;&gt;&amp;.txt</a>"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("<a href=\"../\">../</a>"));
+
+        let _ = std::fs::remove_dir_all(&listed_dir);
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(super::escape_html("<a href=\"x\">'&'</a>"), "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;");
+        assert_eq!(super::percent_encode("a b/c?d#e%f~g.h-i_j"), "a%20b%2Fc%3Fd%23e%25f~g.h-i_j");
+        assert_eq!(super::percent_encode("caf\u{e9}"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(super::format_size(0), "0 B");
+        assert_eq!(super::format_size(1023), "1023 B");
+        assert_eq!(super::format_size(1536), "1.5 KiB");
+        assert_eq!(super::format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn formats_utc_times() {
+        let utc_time = |unix_secs: u64| super::format_utc_time(std::time::UNIX_EPOCH + std::time::Duration::from_secs(unix_secs));
+
+        assert_eq!(utc_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_time(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(utc_time(1706745599), "2024-01-31T23:59:59Z");
+        assert_eq!(utc_time(1709251199), "2024-02-29T23:59:59Z");
+        assert_eq!(utc_time(4107542399), "2100-02-28T23:59:59Z");
+        assert_eq!(utc_time(253402300799), "9999-12-31T23:59:59Z");
+        // Times before the epoch can't come from file metadata on most systems, and are shown as the epoch.
+        assert_eq!(super::format_utc_time(std::time::UNIX_EPOCH - std::time::Duration::from_secs(1)), "1970-01-01T00:00:00Z");
+    }
+}
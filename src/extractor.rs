@@ -0,0 +1,631 @@
+/// Why a handler's arguments couldn't be extracted from a request.
+/// It's answered as a JSON response, e.g. `{"error": "Missing header user-agent"}`, instead of calling the handler.
+#[derive(Debug, Clone)]
+pub struct ExtractError {
+    pub status: http::StatusCode,
+    pub message: String,
+}
+
+impl ExtractError {
+    pub fn bad_request(message: String) -> Self {
+        ExtractError {
+            status: http::StatusCode::BAD_REQUEST,
+            message,
+        }
+    }
+
+    pub fn to_response(&self) -> crate::Response {
+        let error_json = serde_json::json!({
+            "error": self.message,
+        });
+        crate::create_bytes_response(self.status, http::HeaderValue::from_static("application/json"), error_json.to_string().as_bytes())
+    }
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// A value that can be taken from a request, so that handlers can declare it as an argument; see `extract()`.
+pub trait FromRequest: Sized {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError>;
+
+    /// For an `Option<Self>` argument: `None` if the request just doesn't have the value (e.g. a missing header),
+    /// but still an error if it has an invalid one. By default the value is never missing.
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        Self::from_request(request).map(Some)
+    }
+}
+
+/// An optional argument is `None` when the request doesn't have the value, rather than failing the request.
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        T::from_request_optional(request)
+    }
+}
+
+impl FromRequest for http::Method {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        Ok(request.method().clone())
+    }
+}
+
+impl FromRequest for http::Uri {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        Ok(request.uri().clone())
+    }
+}
+
+impl FromRequest for http::HeaderMap {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        Ok(request.headers().clone())
+    }
+}
+
+/// A value shared with every request by `ServerBuilder::state()` or `Router::state()`, e.g. a connection pool or configuration.
+///
+/// Middleware can get it with `State::<T>::from_request(&request)`, like handlers do through `extract()`.
+#[derive(Debug)]
+pub struct State<T>(pub std::sync::Arc<T>);
+
+impl<T> Clone for State<T> {
+    fn clone(&self) -> Self {
+        State(self.0.clone())
+    }
+}
+
+impl<T> std::ops::Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        match request.extensions().get::<State<T>>() {
+            Some(state) => Ok(state.clone()),
+            None => Err(ExtractError {
+                status: http::StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("No state of type {} was added to the server or router", std::any::type_name::<T>()),
+            })
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        Ok(request.extensions().get::<State<T>>().cloned())
+    }
+}
+
+/// The parameters a `Router` captured from the request path, e.g. `Path<u64>` for "/users/:id",
+/// `Path<(String, u64)>` for "/users/:name/posts/:id", or a struct with a field for each parameter's name.
+#[derive(Debug, Clone)]
+pub struct Path<T>(pub T);
+
+impl<T: serde::de::DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        let Some(path_params) = request.extensions().get::<crate::PathParams>() else {
+            return Err(ExtractError {
+                status: http::StatusCode::INTERNAL_SERVER_ERROR,
+                message: "No path parameters, the handler isn't routed by a Router".to_string(),
+            });
+        };
+
+        let params: Vec<(&str, &str)> = path_params.iter().collect();
+        match T::deserialize(PathDeserializer { params: &params }) {
+            Ok(value) => Ok(Path(value)),
+            Err(path_error) => Err(ExtractError::bad_request(format!("Invalid path parameters: {}", path_error)))
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        if request.extensions().get::<crate::PathParams>().is_none_or(|path_params| path_params.iter().next().is_none()) {
+            return Ok(None);
+        }
+        Self::from_request(request).map(Some)
+    }
+}
+
+/// The request's query string, e.g. a struct with `page` and `per_page` fields for "?page=2&per_page=50".
+#[derive(Debug, Clone)]
+pub struct Query<T>(pub T);
+
+impl<T: serde::de::DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        let query = request.uri().query().unwrap_or("");
+        match serde_urlencoded::from_str(query) {
+            Ok(value) => Ok(Query(value)),
+            Err(query_error) => Err(ExtractError::bad_request(format!("Invalid query string: {}", query_error)))
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        if request.uri().query().is_none_or(|query| query.is_empty()) {
+            return Ok(None);
+        }
+        Self::from_request(request).map(Some)
+    }
+}
+
+/// A header that `Header<T>` can extract.
+pub trait NamedHeader: Sized {
+    fn name() -> http::HeaderName;
+
+    /// The header's value, or an explanation of why it isn't valid.
+    fn decode(header_value: &http::HeaderValue) -> Result<Self, String>;
+}
+
+/// Defines a `NamedHeader` for a header whose value is just text.
+macro_rules! text_header {
+    ($(#[$header_doc:meta])* $header_type:ident, $header_name:expr) => {
+        $(#[$header_doc])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $header_type(pub String);
+
+        impl NamedHeader for $header_type {
+            fn name() -> http::HeaderName {
+                $header_name
+            }
+
+            fn decode(header_value: &http::HeaderValue) -> Result<Self, String> {
+                match header_value.to_str() {
+                    Ok(value_str) => Ok($header_type(value_str.to_string())),
+                    Err(_) => Err("not visible ASCII text".to_string())
+                }
+            }
+        }
+    };
+}
+
+text_header!(
+    /// The `Accept` header, e.g. "text/html, application/json;q=0.9".
+    Accept, http::header::ACCEPT);
+text_header!(
+    /// The `Authorization` header, e.g. "Bearer abc123".
+    Authorization, http::header::AUTHORIZATION);
+text_header!(
+    /// The `Content-Type` header, e.g. "application/json".
+    ContentType, http::header::CONTENT_TYPE);
+text_header!(
+    /// The `Host` header, e.g. "example.com:8080".
+    Host, http::header::HOST);
+text_header!(
+    /// The `User-Agent` header, e.g. "curl/8.5.0".
+    UserAgent, http::header::USER_AGENT);
+
+/// A request header, e.g. `Header<UserAgent>`; a request without it fails with 400, unless it's an `Option<Header<T>>`.
+#[derive(Debug, Clone)]
+pub struct Header<T>(pub T);
+
+impl<T: NamedHeader> FromRequest for Header<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        let header_name = T::name();
+        let Some(header_value) = request.headers().get(&header_name) else {
+            return Err(ExtractError::bad_request(format!("Missing header {}", header_name)));
+        };
+
+        match T::decode(header_value) {
+            Ok(value) => Ok(Header(value)),
+            Err(header_error) => Err(ExtractError::bad_request(format!("Invalid header {}: {}", header_name, header_error)))
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        if !request.headers().contains_key(T::name()) {
+            return Ok(None);
+        }
+        Self::from_request(request).map(Some)
+    }
+}
+
+/// A JSON request body; requests with a different `Content-Type` fail with 415.
+#[derive(Debug, Clone)]
+pub struct Json<T>(pub T);
+
+impl<T: serde::de::DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        // Besides application/json itself, there are JSON-based types like "application/problem+json".
+        let is_json = request_media_type(request).is_some_and(|media_type| {
+            media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+        });
+        if !is_json {
+            return Err(ExtractError {
+                status: http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                message: "Expected a request body with Content-Type application/json".to_string(),
+            });
+        }
+
+        match serde_json::from_slice(request.body()) {
+            Ok(value) => Ok(Json(value)),
+            Err(json_error) => Err(ExtractError::bad_request(format!("Invalid JSON body: {}", json_error)))
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        if request_has_no_body(request) {
+            return Ok(None);
+        }
+        Self::from_request(request).map(Some)
+    }
+}
+
+/// A URL-encoded form request body; requests with a different `Content-Type` fail with 415.
+#[derive(Debug, Clone)]
+pub struct Form<T>(pub T);
+
+impl<T: serde::de::DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(request: &crate::Request) -> Result<Self, ExtractError> {
+        if request_media_type(request).as_deref() != Some("application/x-www-form-urlencoded") {
+            return Err(ExtractError {
+                status: http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                message: "Expected a request body with Content-Type application/x-www-form-urlencoded".to_string(),
+            });
+        }
+
+        match serde_urlencoded::from_bytes(request.body()) {
+            Ok(value) => Ok(Form(value)),
+            Err(form_error) => Err(ExtractError::bad_request(format!("Invalid form body: {}", form_error)))
+        }
+    }
+
+    fn from_request_optional(request: &crate::Request) -> Result<Option<Self>, ExtractError> {
+        if request_has_no_body(request) {
+            return Ok(None);
+        }
+        Self::from_request(request).map(Some)
+    }
+}
+
+fn request_has_no_body(request: &crate::Request) -> bool {
+    request.body().is_empty() && !request.headers().contains_key(http::header::CONTENT_TYPE)
+}
+
+fn request_media_type(request: &crate::Request) -> Option<String> {
+    // A Content-Type like "application/json; charset=utf-8" has parameters after the media type, which don't matter here.
+    let content_type = request.headers().get(http::header::CONTENT_TYPE)?.to_str().ok()?;
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    Some(media_type.to_ascii_lowercase())
+}
+
+/// A function whose arguments can all be extracted from a request; see `extract()`.
+pub trait ExtractorFn<Args>: Send + Sync + 'static {
+    fn call_extracted(&self, request: &crate::Request) -> Result<crate::Response, ExtractError>;
+}
+
+macro_rules! impl_extractor_fn {
+    ($($arg_type:ident),+) => {
+        impl<F, $($arg_type,)+> ExtractorFn<($($arg_type,)+)> for F
+        where F: Fn($($arg_type),+) -> crate::Response + Send + Sync + 'static,
+            $($arg_type: FromRequest,)+ {
+            fn call_extracted(&self, request: &crate::Request) -> Result<crate::Response, ExtractError> {
+                Ok(self($($arg_type::from_request(request)?),+))
+            }
+        }
+    };
+}
+
+impl_extractor_fn!(T1);
+impl_extractor_fn!(T1, T2);
+impl_extractor_fn!(T1, T2, T3);
+impl_extractor_fn!(T1, T2, T3, T4);
+impl_extractor_fn!(T1, T2, T3, T4, T5);
+impl_extractor_fn!(T1, T2, T3, T4, T5, T6);
+
+/// A handler made from a function taking extractors as its arguments; see `extract()`.
+pub struct Extract<F, Args> {
+    extractor_fn: F,
+    args: std::marker::PhantomData<fn(Args)>,
+}
+
+impl<F, Args> crate::Handler for Extract<F, Args>
+where F: ExtractorFn<Args>, Args: 'static {
+    fn call(&self, request: crate::Request) -> crate::Response {
+        match self.extractor_fn.call_extracted(&request) {
+            Ok(response) => response,
+            Err(extract_error) => {
+                log::warn!("Request extraction error: {}", extract_error);
+                extract_error.to_response()
+            }
+        }
+    }
+}
+
+/// Make a handler from a function that takes up to six extractors as its arguments, e.g.
+/// `Router::new().get("/users/:id", extract(|Path(id): Path<u64>, Query(paging): Query<Paging>| ...))`.
+///
+/// The arguments are extracted in order, and the first that fails is answered with its error, without calling the function.
+pub fn extract<F, Args>(extractor_fn: F) -> Extract<F, Args>
+where F: ExtractorFn<Args> {
+    Extract {
+        extractor_fn,
+        args: std::marker::PhantomData,
+    }
+}
+
+/// Deserializes path parameters as a map (for structs), a sequence (for tuples), or (when there's only one) a single value.
+struct PathDeserializer<'de> {
+    params: &'de [(&'de str, &'de str)],
+}
+
+impl<'de> PathDeserializer<'de> {
+    fn single_value(&self) -> Result<PathValueDeserializer<'de>, serde::de::value::Error> {
+        match self.params {
+            [(_, param_value)] => Ok(PathValueDeserializer(param_value)),
+            _ => Err(serde::de::Error::custom(format!("expected 1 parameter, found {}", self.params.len())))
+        }
+    }
+}
+
+macro_rules! deserialize_single_value {
+    ($($deserialize_method:ident),* $(,)?) => {
+        $(
+            fn $deserialize_method<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.single_value()?.$deserialize_method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> serde::Deserializer<'de> for PathDeserializer<'de> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let entries = self.params.iter().map(|(param_name, param_value)| (*param_name, PathValueDeserializer(param_value)));
+        visitor.visit_map(serde::de::value::MapDeserializer::new(entries))
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let values = self.params.iter().map(|(_, param_value)| PathValueDeserializer(param_value));
+        let mut seq_deserializer = serde::de::value::SeqDeserializer::new(values);
+        let value = visitor.visit_seq(&mut seq_deserializer)?;
+        seq_deserializer.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: serde::de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.single_value()?.deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_single_value!(
+        deserialize_bool, deserialize_i8, deserialize_i16, deserialize_i32, deserialize_i64, deserialize_i128,
+        deserialize_u8, deserialize_u16, deserialize_u32, deserialize_u64, deserialize_u128, deserialize_f32, deserialize_f64,
+        deserialize_char, deserialize_str, deserialize_string, deserialize_bytes, deserialize_byte_buf,
+        deserialize_option, deserialize_unit, deserialize_identifier, deserialize_ignored_any,
+    );
+
+    fn deserialize_unit_struct<V: serde::de::Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        self.single_value()?.deserialize_unit_struct(name, visitor)
+    }
+}
+
+/// Deserializes one path parameter, parsing it as whatever type is wanted.
+struct PathValueDeserializer<'de>(&'de str);
+
+macro_rules! deserialize_parsed_value {
+    ($($deserialize_method:ident => $visit_method:ident),* $(,)?) => {
+        $(
+            fn $deserialize_method<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse() {
+                    Ok(parsed_value) => visitor.$visit_method(parsed_value),
+                    Err(_) => Err(serde::de::Error::invalid_value(serde::de::Unexpected::Str(self.0), &visitor))
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> serde::Deserializer<'de> for PathValueDeserializer<'de> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    deserialize_parsed_value!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8, deserialize_i16 => visit_i16, deserialize_i32 => visit_i32, deserialize_i64 => visit_i64, deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8, deserialize_u16 => visit_u16, deserialize_u32 => visit_u32, deserialize_u64 => visit_u64, deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32, deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    );
+
+    fn deserialize_option<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        // Only unit variants can be named by a path segment, e.g. "/posts/:sort" with "newest" or "oldest".
+        visitor.visit_enum(serde::de::value::BorrowedStrDeserializer::new(self.0))
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> serde::de::IntoDeserializer<'de, serde::de::value::Error> for PathValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[derive(serde::Deserialize)]
+    struct PostPath {
+        name: String,
+        id: u64,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Paging {
+        page: u32,
+        per_page: Option<u32>,
+    }
+
+    fn request_with(content_type: Option<&str>, body: &str) -> crate::Request {
+        let mut request_builder = http::Request::builder().method(http::Method::POST).uri("/");
+        if let Some(content_type) = content_type {
+            request_builder = request_builder.header(http::header::CONTENT_TYPE, content_type);
+        }
+        request_builder.body(body.as_bytes().to_vec()).unwrap()
+    }
+
+    fn text_response(text: &str) -> crate::Response {
+        crate::create_text_response(http::StatusCode::OK, text)
+    }
+
+    #[test]
+    fn deserializes_path_params() {
+        let router = crate::Router::new()
+            .get("/users/:id", super::extract(|super::Path(id): super::Path<u64>| text_response(&format!("{}", id + 1))))
+            .get("/tuple/:name/:id", super::extract(|super::Path((name, id)): super::Path<(String, u64)>| text_response(&format!("{} {}", name, id))))
+            .get("/struct/:name/:id", super::extract(|super::Path(post_path): super::Path<PostPath>| text_response(&format!("{} {}", post_path.name, post_path.id))));
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/41")), "42");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/tuple/a%20b/7")), "a b 7");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/struct/x/8")), "x 8");
+    }
+
+    #[test]
+    fn rejects_unparseable_path_params() {
+        let router = crate::Router::new()
+            .get("/users/:id", super::extract(|super::Path(id): super::Path<u64>| text_response(&format!("{}", id))))
+            .get("/pair/:id", super::extract(|super::Path(pair): super::Path<(u64, u64)>| text_response(&format!("{:?}", pair))));
+
+        let response = crate::test_support::call(&router, http::Method::GET, "/users/abc");
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[http::header::CONTENT_TYPE], "application/json");
+        assert!(crate::test_support::body_text(&response).starts_with("{\"error\":\"Invalid path parameters"));
+        assert_eq!(crate::test_support::call(&router, http::Method::GET, "/pair/1").status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn deserializes_query() {
+        let handler = super::extract(|super::Query(paging): super::Query<Paging>| text_response(&format!("{} {:?}", paging.page, paging.per_page)));
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&handler, http::Method::GET, "/?page=2&per_page=50")), "2 Some(50)");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&handler, http::Method::GET, "/?page=3")), "3 None");
+        assert_eq!(crate::test_support::call(&handler, http::Method::GET, "/?page=x").status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(crate::test_support::call(&handler, http::Method::GET, "/").status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn extracts_headers() {
+        let handler = super::extract(|super::Header(user_agent): super::Header<super::UserAgent>| text_response(&user_agent.0));
+
+        let request = http::Request::builder().uri("/").header(http::header::USER_AGENT, "test/1.0").body(Vec::new()).unwrap();
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&handler, request)), "test/1.0");
+
+        let response = crate::test_support::call(&handler, http::Method::GET, "/");
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(crate::test_support::body_text(&response), "{\"error\":\"Missing header user-agent\"}");
+    }
+
+    #[test]
+    fn checks_json_content_type() {
+        let handler = super::extract(|super::Json(value): super::Json<serde_json::Value>| text_response(&value["name"].to_string()));
+
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&handler, request_with(Some("application/json"), "{\"name\":\"a\"}"))), "\"a\"");
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&handler, request_with(Some("Application/Problem+JSON; charset=utf-8"), "{\"name\":1}"))), "1");
+
+        let response = crate::Handler::call(&handler, request_with(Some("text/plain"), "{}"));
+        assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(crate::test_support::body_text(&response), "{\"error\":\"Expected a request body with Content-Type application/json\"}");
+        assert_eq!(crate::Handler::call(&handler, request_with(None, "{}")).status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let response = crate::Handler::call(&handler, request_with(Some("application/json"), "{oops"));
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        assert!(crate::test_support::body_text(&response).starts_with("{\"error\":\"Invalid JSON body: "));
+    }
+
+    #[test]
+    fn checks_form_content_type() {
+        let handler = super::extract(|super::Form(paging): super::Form<Paging>| text_response(&format!("{} {:?}", paging.page, paging.per_page)));
+
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&handler, request_with(Some("application/x-www-form-urlencoded"), "page=4"))), "4 None");
+        assert_eq!(crate::Handler::call(&handler, request_with(Some("application/json"), "page=4")).status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(crate::Handler::call(&handler, request_with(Some("application/x-www-form-urlencoded"), "page=four")).status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn optional_extractors_are_none_only_when_missing() {
+        let json_handler = super::extract(|json: Option<super::Json<serde_json::Value>>| text_response(&format!("{:?}", json.map(|super::Json(value)| value))));
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&json_handler, request_with(None, ""))), "None");
+        assert_eq!(crate::test_support::body_text(&crate::Handler::call(&json_handler, request_with(Some("application/json"), "1"))), "Some(Number(1))");
+        assert_eq!(crate::Handler::call(&json_handler, request_with(Some("application/json"), "{oops")).status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(crate::Handler::call(&json_handler, request_with(Some("text/plain"), "1")).status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let query_handler = super::extract(|paging: Option<super::Query<Paging>>| text_response(&format!("{:?}", paging.map(|super::Query(paging)| paging.page))));
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&query_handler, http::Method::GET, "/")), "None");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&query_handler, http::Method::GET, "/?page=5")), "Some(5)");
+        assert_eq!(crate::test_support::call(&query_handler, http::Method::GET, "/?page=x").status(), http::StatusCode::BAD_REQUEST);
+
+        let header_handler = super::extract(|host: Option<super::Header<super::Host>>| text_response(&format!("{:?}", host.map(|super::Header(host)| host.0))));
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&header_handler, http::Method::GET, "/")), "None");
+        let request = http::Request::builder().uri("/").header(http::header::HOST, http::HeaderValue::from_bytes(b"caf\xe9").unwrap()).body(Vec::new()).unwrap();
+        assert_eq!(crate::Handler::call(&header_handler, request).status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn nested_router_state_replaces_servers() {
+        let show_state = || super::extract(|name: super::State<String>, number: super::State<u64>| text_response(&format!("{} {}", *name, *number)));
+        let router = crate::Router::new()
+            .get("/name", show_state())
+            .nest("/admin", crate::Router::new().get("/name", show_state()).state("admin".to_string()));
+        let mut server_states = http::Extensions::new();
+        server_states.insert(super::State(std::sync::Arc::new("server".to_string())));
+        server_states.insert(super::State(std::sync::Arc::new(1_u64)));
+        let server = crate::middleware::Layered {
+            states: server_states,
+            middleware: Vec::new(),
+            handler: std::sync::Arc::new(router),
+        };
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&server, http::Method::GET, "/name")), "server 1");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&server, http::Method::GET, "/admin/name")), "admin 1");
+    }
+
+    #[test]
+    fn answers_500_for_missing_state() {
+        let handler = super::extract(|number: super::State<u64>| text_response(&number.to_string()));
+        let response = crate::test_support::call(&handler, http::Method::GET, "/");
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[http::header::CONTENT_TYPE], "application/json");
+        assert_eq!(crate::test_support::body_text(&response), "{\"error\":\"No state of type u64 was added to the server or router\"}");
+
+        let optional_handler = super::extract(|number: Option<super::State<u64>>| text_response(&format!("{:?}", number.map(|number| *number))));
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&optional_handler, http::Method::GET, "/")), "None");
+    }
+}
@@ -0,0 +1,19 @@
+/// The request type handlers receive, with the body read in full.
+pub type Request = http::Request<Vec<u8>>;
+
+/// The response type handlers return.
+pub type Response = http::Response<Vec<u8>>;
+
+/// Turns a request into a response.
+///
+/// Any `Fn(Request) -> Response` closure or function is a handler, and so is a `Router`.
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, request: Request) -> Response;
+}
+
+impl<F> Handler for F
+where F: Fn(Request) -> Response + Send + Sync + 'static {
+    fn call(&self, request: Request) -> Response {
+        self(request)
+    }
+}
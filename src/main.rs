@@ -1,133 +1,209 @@
-use http;
-use log;
-use structured_logger;
+mod cli;
+mod config;
 
-struct ServerControl {
-    should_stop: bool,
+fn handle_hello(_request: lrn2rust_httpserver::Request) -> lrn2rust_httpserver::Response {
+    lrn2rust_httpserver::create_text_response(http::StatusCode::OK, "Hello!")
 }
 
-fn handle_request_stream(request_stream: &mut std::net::TcpStream) -> ServerControl {
-    let mut control_result = ServerControl {
-        should_stop: false,
-    };
+fn handle_hello_name(lrn2rust_httpserver::Path(name): lrn2rust_httpserver::Path<String>) -> lrn2rust_httpserver::Response {
+    lrn2rust_httpserver::create_text_response(http::StatusCode::OK, &format!("Hello, {}!", name))
+}
 
-    let mut request_http_version: http::Version = http::Version::HTTP_10;
-    let response: http::Response<String>;
-    match lrn2rust_httpserver::read_http_request(request_stream) {
-        Ok(request) => {
-            log::info!("Read request: {} {}", request.method(), request.uri());
-
-            request_http_version = request.version();
-
-            let response_status: http::StatusCode;
-            let response_body: String;
-            match request.uri().path() {
-                "/" => {
-                    response_status = http::StatusCode::OK;
-                    response_body = "Hello!".parse().unwrap();
-                },
-                "/stop" => {
-                    control_result.should_stop = true;
-
-                    response_status = http::StatusCode::OK;
-                    response_body = "Goodbye.".parse().unwrap();
-                },
-                _ => {
-                    response_status = http::StatusCode::NOT_FOUND;
-                    response_body = format!("Unrecognized path {}", request.uri().path());
-                }
-            }
+fn handle_stop(_request: lrn2rust_httpserver::Request) -> lrn2rust_httpserver::Response {
+    let mut response = lrn2rust_httpserver::create_text_response(http::StatusCode::OK, "Goodbye.");
+    response.extensions_mut().insert(lrn2rust_httpserver::ServerControl {
+        should_stop: true,
+    });
 
-            response = lrn2rust_httpserver::create_text_response(response_status, response_body.as_str());
-        },
-        Err(read_error) => {
-            log::error!("Request read error: {}", read_error);
+    response
+}
 
-            let response_body = &read_error.to_string();
+fn add_server_header(request: lrn2rust_httpserver::Request, next: lrn2rust_httpserver::Next) -> lrn2rust_httpserver::Response {
+    let mut response = next.run(request);
+    response.headers_mut().insert(http::header::SERVER, http::HeaderValue::from_static("lrn2rust-httpserver"));
 
-            response = lrn2rust_httpserver::create_text_response(http::StatusCode::BAD_REQUEST, response_body);
-        }
-    }
-
-    let response_writestream = request_stream as &mut dyn std::io::Write;
+    response
+}
 
-    let http_version_string: &str;
-    match request_http_version {
-        http::Version::HTTP_09 => {
-            http_version_string = "HTTP/0.9";
-        },
-        http::Version::HTTP_10 => {
-            http_version_string = "HTTP/1.0";
-        },
-        http::Version::HTTP_11 => {
-            http_version_string = "HTTP/1.1";
-        },
-        http::Version::HTTP_2 => {
-            http_version_string = "HTTP/2.0";
-        },
-        http::Version::HTTP_3 => {
-            http_version_string = "HTTP/3.0";
-        },
-        _ => {
-            log::warn!("Unrecognized request protocol, falling back to HTTP/1.0");
-            http_version_string = "HTTP/1.0";
-        }
+fn build_server(server_config: &config::ServerConfig, listener: &config::ListenerConfig, handler: std::sync::Arc<dyn lrn2rust_httpserver::Handler>) -> Result<lrn2rust_httpserver::Server, std::io::Error> {
+    let mut server_builder = lrn2rust_httpserver::Server::builder()
+        .bind((listener.bind.as_str(), listener.port))
+        .handler(move |request: lrn2rust_httpserver::Request| handler.call(request))
+        .layer(add_server_header)
+        .parser_limits(server_config.parser_limits.clone());
+    if let Some(workers) = server_config.workers {
+        server_builder = server_builder.workers(workers);
     }
-    let write_result = write!(response_writestream, "{} {} {}\r\n", http_version_string, response.status().as_str(), response.status().canonical_reason().unwrap_or(""));
-    if write_result.is_err() {
-        log::error!("Response write error: {}", write_result.unwrap_err());
-        return control_result;
+    if let Some(queue_depth) = server_config.queue_depth {
+        server_builder = server_builder.queue_depth(queue_depth);
     }
-
-    for response_header in response.headers() {
-        let write_result = write!(response_writestream, "{}: {}\r\n", response_header.0, response_header.1.to_str().unwrap_or(""));
-        if write_result.is_err() {
-            log::error!("Response write error: {}", write_result.unwrap_err());
-            return control_result;
-        }
+    if let Some(max_requests) = server_config.max_requests_per_connection {
+        server_builder = server_builder.max_requests_per_connection(max_requests);
     }
-    let write_result = write!(response_writestream, "\r\n");
-    if write_result.is_err() {
-        log::error!("Response write error: {}", write_result.unwrap_err());
-        return control_result;
+    if let Some(read_timeout) = server_config.read_timeout {
+        server_builder = server_builder.read_timeout(read_timeout);
     }
-
-    let write_result = write!(response_writestream, "{}\r\n", response.body().to_string());
-    if write_result.is_err() {
-        log::error!("Response write error: {}", write_result.unwrap_err());
-        return control_result;
+    if let Some(idle_timeout) = server_config.idle_timeout {
+        server_builder = server_builder.idle_timeout(idle_timeout);
+    }
+    if let Some(shutdown_timeout) = server_config.shutdown_timeout {
+        server_builder = server_builder.shutdown_grace_period(shutdown_timeout);
     }
 
-    return control_result;
+    server_builder.build()
 }
 
 fn main() {
-    let log_writer = structured_logger::json::new_writer(std::io::stdout());
-    structured_logger::Builder::new().with_default_writer(log_writer).init();
+    let args = match cli::parse_args(std::env::args().skip(1), |env_var| std::env::var(env_var).ok()) {
+        Ok(args) => args,
+        Err(args_error) => {
+            eprintln!("{}\n\n{}", args_error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let cli::Command::Help = args.command {
+        print!("{}", cli::USAGE);
+        return;
+    }
 
-    log::info!("Starting TCP listener");
+    let options = args.options;
+    let mut server_config = match &options.config {
+        Some(config_path) => match config::ServerConfig::load(config_path) {
+            Ok(server_config) => server_config,
+            Err(config_error) => {
+                eprintln!("{}", config_error);
+                std::process::exit(2);
+            }
+        },
+        None => config::ServerConfig::default(),
+    };
+
+    // Options from the command line or environment win over the config file's settings.
+    server_config.workers = options.workers.or(server_config.workers);
+    server_config.read_timeout = options.read_timeout.or(server_config.read_timeout);
+    server_config.idle_timeout = options.idle_timeout.or(server_config.idle_timeout);
+    server_config.shutdown_timeout = options.shutdown_timeout.or(server_config.shutdown_timeout);
+    server_config.log_level = options.log_level.or(server_config.log_level);
 
-    let tcp_listener = std::net::TcpListener::bind("0.0.0.0:8080").unwrap();
-    for listen_result in tcp_listener.incoming() {
-        let (handler_txchan, handler_rxchan) = std::sync::mpsc::channel();
+    let log_writer = structured_logger::json::new_writer(std::io::stdout());
+    let log_builder = match server_config.log_level {
+        Some(log_level) => structured_logger::Builder::with_level(log_level.as_str()),
+        None => structured_logger::Builder::new(),
+    };
+    log_builder.with_default_writer(log_writer).init();
 
-        match listen_result {
-            Ok(mut stream) => {
-                std::thread::spawn(move || {
-                    let handler_result = handle_request_stream(&mut stream);
-                    handler_txchan.send(handler_result).unwrap();
-                });
+    let (handler, default_port): (std::sync::Arc<dyn lrn2rust_httpserver::Handler>, u16) = match args.command {
+        cli::Command::Demo if !server_config.routes.is_empty() => {
+            (std::sync::Arc::new(server_config.router()), 8080)
+        },
+        cli::Command::Demo => {
+            let demo_router = lrn2rust_httpserver::Router::new()
+                .get("/", handle_hello)
+                .get("/hello/:name", lrn2rust_httpserver::extract(handle_hello_name))
+                .get("/stop", handle_stop);
+            (std::sync::Arc::new(demo_router), 8080)
+        },
+        cli::Command::Serve { dir } => {
+            if !server_config.routes.is_empty() {
+                eprintln!("Can't serve a directory with a config file that has routes");
+                std::process::exit(2);
+            }
+            if !dir.is_dir() {
+                eprintln!("Can't serve {}, it isn't a directory", dir.display());
+                std::process::exit(1);
             }
-            Err(error) => {
-                log::error!("TCP listener error: {}", error);
+
+            log::info!("Serving directory {}", dir.display());
+            (std::sync::Arc::new(lrn2rust_httpserver::StaticFiles::new(dir).listing(true)), 8000)
+        },
+        cli::Command::Help => unreachable!("Help was already shown"),
+    };
+
+    // A bind address or port from the command line or environment replaces the config file's listeners with just one.
+    if options.bind.is_some() || options.port.is_some() || server_config.listeners.is_empty() {
+        let first_listener = server_config.listeners.first();
+        let listener = config::ListenerConfig {
+            bind: options.bind.or_else(|| first_listener.map(|listener| listener.bind.clone())).unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: options.port.or_else(|| first_listener.map(|listener| listener.port)).unwrap_or(default_port),
+        };
+        server_config.listeners = vec![listener];
+    }
+
+    let mut servers = Vec::with_capacity(server_config.listeners.len());
+    for listener in &server_config.listeners {
+        match build_server(&server_config, listener, handler.clone()) {
+            Ok(server) => {
+                // With port 0, this is the only place to find out which port the server actually got,
+                // so it's shown whatever the log level.
+                if let Ok(local_addr) = server.local_addr() {
+                    eprintln!("Listening on http://{}/", local_addr);
+                    log::info!("Listening on http://{}/", local_addr);
+                }
+                servers.push(server);
+            },
+            Err(server_error) => {
+                eprintln!("Couldn't start server: {}", server_error);
+                std::process::exit(1);
             }
         }
+    }
 
-        let control_result = handler_rxchan.recv().unwrap();
-        if control_result.should_stop {
-            break;
+    // SIGINT and SIGTERM stop the servers gracefully; a second one exits immediately, without waiting.
+    let server_handles: Vec<lrn2rust_httpserver::ServerHandle> = servers.iter().map(|server| server.handle()).collect();
+    let signal_server_handles = server_handles.clone();
+    match signal_hook::iterator::Signals::new([signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM]) {
+        Ok(mut signals) => {
+            std::thread::spawn(move || {
+                for signal in signals.forever() {
+                    if signal_server_handles.iter().any(|server_handle| server_handle.is_stopping()) {
+                        log::warn!("Received signal {} while stopping, exiting now", signal);
+                        std::process::exit(1);
+                    }
+
+                    log::info!("Received signal {}, stopping", signal);
+                    for server_handle in &signal_server_handles {
+                        server_handle.stop();
+                    }
+                }
+            });
+        },
+        Err(signal_error) => {
+            log::error!("Signal handler error: {}", signal_error);
         }
     }
 
-    log::info!("Shutting down");
+    // Each listener runs its own server; once any of them stops, so do the rest.
+    let (stopped_txchan, stopped_rxchan) = std::sync::mpsc::channel::<()>();
+    let server_threads: Vec<std::thread::JoinHandle<Result<(), std::io::Error>>> = servers.into_iter().map(|server| {
+        let server_stopped_txchan = stopped_txchan.clone();
+        std::thread::spawn(move || {
+            let run_result = server.run();
+            let _ = server_stopped_txchan.send(());
+            run_result
+        })
+    }).collect();
+    drop(stopped_txchan);
+
+    let _ = stopped_rxchan.recv();
+    for server_handle in &server_handles {
+        server_handle.stop();
+    }
+
+    let mut server_failed = false;
+    for server_thread in server_threads {
+        match server_thread.join() {
+            Ok(Ok(())) => {},
+            Ok(Err(server_error)) => {
+                log::error!("Server error: {}", server_error);
+                server_failed = true;
+            },
+            Err(_) => {
+                log::error!("Server thread panicked");
+                server_failed = true;
+            }
+        }
+    }
+    if server_failed {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,148 @@
+/// Wraps the handling of a request, e.g. to check authorization, log requests, or add response headers.
+///
+/// A middleware can inspect or change the request before passing it on with `next.run(request)`,
+/// transform the response that comes back, or skip `next` entirely and answer the request itself.
+///
+/// Any `Fn(Request, Next) -> Response` closure or function is a middleware.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, request: crate::Request, next: Next) -> crate::Response;
+}
+
+impl<F> Middleware for F
+where F: Fn(crate::Request, Next) -> crate::Response + Send + Sync + 'static {
+    fn handle(&self, request: crate::Request, next: Next) -> crate::Response {
+        self(request, next)
+    }
+}
+
+/// The rest of the chain after a middleware: any later middleware, and then the handler.
+pub struct Next<'a> {
+    middleware: &'a [std::sync::Arc<dyn Middleware>],
+    endpoint: &'a dyn Fn(crate::Request) -> crate::Response,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middleware: &'a [std::sync::Arc<dyn Middleware>], endpoint: &'a dyn Fn(crate::Request) -> crate::Response) -> Self {
+        Next {
+            middleware,
+            endpoint,
+        }
+    }
+
+    /// Pass the request on down the chain, and get back its response.
+    pub fn run(self, request: crate::Request) -> crate::Response {
+        match self.middleware.split_first() {
+            Some((first_middleware, later_middleware)) => {
+                first_middleware.handle(request, Next::new(later_middleware, self.endpoint))
+            },
+            None => (self.endpoint)(request)
+        }
+    }
+}
+
+/// A handler wrapped in middleware, the first of which sees each request first (and its response last).
+/// Its state is added to each request before any of the middleware sees it.
+pub(crate) struct Layered {
+    pub(crate) states: http::Extensions,
+    pub(crate) middleware: Vec<std::sync::Arc<dyn Middleware>>,
+    pub(crate) handler: std::sync::Arc<dyn crate::Handler>,
+}
+
+impl crate::Handler for Layered {
+    fn call(&self, mut request: crate::Request) -> crate::Response {
+        request.extensions_mut().extend(self.states.clone());
+
+        let endpoint = |request| self.handler.call(request);
+        Next::new(&self.middleware, &endpoint).run(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    type Trace = std::sync::Arc<std::sync::Mutex<Vec<String>>>;
+
+    // Records when it sees the request (before running the rest of the chain) and the response (after).
+    fn tracing_layer(name: &'static str, trace: &Trace) -> impl super::Middleware {
+        let trace = trace.clone();
+        move |request: crate::Request, next: super::Next| {
+            trace.lock().unwrap().push(format!("{}>", name));
+            let response = next.run(request);
+            trace.lock().unwrap().push(format!("<{}", name));
+            response
+        }
+    }
+
+    fn traced_handler(trace: &Trace) -> impl crate::Handler {
+        let trace = trace.clone();
+        move |_request: crate::Request| {
+            trace.lock().unwrap().push("handler".to_string());
+            crate::create_text_response(http::StatusCode::OK, "handled")
+        }
+    }
+
+    fn layered(router: crate::Router, trace: &Trace) -> super::Layered {
+        super::Layered {
+            states: http::Extensions::new(),
+            middleware: vec![std::sync::Arc::new(tracing_layer("server1", trace)), std::sync::Arc::new(tracing_layer("server2", trace))],
+            handler: std::sync::Arc::new(router),
+        }
+    }
+
+    #[test]
+    fn runs_server_layers_outside_router_layers() {
+        let trace = Trace::default();
+        let api_router = crate::Router::new()
+            .get("/items", traced_handler(&trace))
+            .layer(tracing_layer("api", &trace));
+        let router = crate::Router::new()
+            .nest("/api", api_router)
+            .layer(tracing_layer("router1", &trace))
+            .layer(tracing_layer("router2", &trace));
+
+        let response = crate::test_support::call(&layered(router, &trace), http::Method::GET, "/api/items");
+        assert_eq!(crate::test_support::body_text(&response), "handled");
+        assert_eq!(*trace.lock().unwrap(), [
+            "server1>", "server2>", "router1>", "router2>", "api>", "handler", "<api", "<router2", "<router1", "<server2", "<server1",
+        ]);
+    }
+
+    #[test]
+    fn runs_router_layers_for_unmatched_requests() {
+        let trace = Trace::default();
+        let router = crate::Router::new()
+            .nest("/api", crate::Router::new().get("/items", traced_handler(&trace)).layer(tracing_layer("api", &trace)))
+            .layer(tracing_layer("router", &trace));
+
+        let response = crate::test_support::call(&layered(router, &trace), http::Method::GET, "/missing");
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+        // A nested router's layers only wrap the requests it handles.
+        assert_eq!(*trace.lock().unwrap(), ["server1>", "server2>", "router>", "<router", "<server2", "<server1"]);
+    }
+
+    #[test]
+    fn layer_can_answer_without_next() {
+        let trace = Trace::default();
+        let router = crate::Router::new()
+            .get("/secret", traced_handler(&trace))
+            .layer(|request: crate::Request, next: super::Next| {
+                if request.headers().contains_key(http::header::AUTHORIZATION) {
+                    next.run(request)
+                } else {
+                    crate::create_text_response(http::StatusCode::UNAUTHORIZED, "Unauthorized")
+                }
+            })
+            .layer(tracing_layer("inner", &trace));
+
+        let server = layered(router, &trace);
+
+        let response = crate::test_support::call(&server, http::Method::GET, "/secret");
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+        assert_eq!(*trace.lock().unwrap(), ["server1>", "server2>", "<server2", "<server1"]);
+
+        trace.lock().unwrap().clear();
+        let mut request = crate::test_support::request(http::Method::GET, "/secret");
+        request.headers_mut().insert(http::header::AUTHORIZATION, http::HeaderValue::from_static("Bearer x"));
+        assert_eq!(crate::Handler::call(&server, request).status(), http::StatusCode::OK);
+        assert_eq!(*trace.lock().unwrap(), ["server1>", "server2>", "inner>", "handler", "<inner", "<server2", "<server1"]);
+    }
+}
@@ -0,0 +1,745 @@
+// Chunk-size lines only need room for a size and some extensions, so their cap isn't configurable.
+const MAX_CHUNK_LINE_LEN: usize = 4 * 1024;
+
+/// Size limits applied while parsing a request, so that a client can't grow the parser's buffers without bound.
+#[derive(Debug, Clone)]
+pub struct ParserLimits {
+    /// The longest request target (path and query) allowed, in bytes.
+    pub max_uri_len: usize,
+    /// The most header lines allowed, including trailers on a chunked body.
+    pub max_header_count: usize,
+    /// The longest single header line allowed, in bytes.
+    pub max_header_line_len: usize,
+    /// The most header bytes allowed across all header lines.
+    pub max_headers_len: usize,
+    /// The largest request body allowed, in bytes.
+    pub max_body_len: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        ParserLimits {
+            max_uri_len: 8 * 1024,
+            max_header_count: 100,
+            max_header_line_len: 8 * 1024,
+            max_headers_len: 64 * 1024,
+            max_body_len: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// The ways reading an HTTP request can fail.
+#[derive(Debug)]
+pub enum RequestParseError {
+    /// The start line isn't made up of a method, target, and protocol.
+    BadStartLine,
+    /// The request method isn't a valid token.
+    BadMethod,
+    /// The request target isn't a valid URI for the request method.
+    BadTarget,
+    /// The request target is longer than the parser allows.
+    UriTooLong,
+    /// The protocol isn't formatted like "HTTP/1.1".
+    BadVersion,
+    /// The protocol is a version of HTTP other than 1.x.
+    UnsupportedVersion,
+    /// A header line has no ':', or its name isn't a valid token.
+    BadHeaderName,
+    /// A header value contains invalid bytes.
+    BadHeaderValue,
+    /// The request headers are longer or more numerous than the parser allows.
+    HeadersTooLarge,
+    /// The request body is longer than the parser allows.
+    ContentTooLarge,
+    /// The Content-Length header is invalid or ambiguous.
+    BadContentLength,
+    /// The Transfer-Encoding header is invalid, unsupported, or conflicts with Content-Length.
+    BadTransferEncoding,
+    /// A chunk of a chunked body is malformed.
+    BadChunk,
+    /// The transport closed before the request was complete.
+    Truncated,
+    /// The transport failed while reading the request.
+    Io(std::io::Error),
+}
+
+impl RequestParseError {
+    /// The response status that best describes this error to the client.
+    pub fn status(&self) -> http::StatusCode {
+        match self {
+            RequestParseError::UriTooLong => http::StatusCode::URI_TOO_LONG,
+            RequestParseError::UnsupportedVersion => http::StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            RequestParseError::HeadersTooLarge => http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            RequestParseError::ContentTooLarge => http::StatusCode::PAYLOAD_TOO_LARGE,
+            _ => http::StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+impl std::fmt::Display for RequestParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestParseError::BadStartLine => write!(f, "Failed to parse HTTP request start line"),
+            RequestParseError::BadMethod => write!(f, "Failed to parse HTTP request method"),
+            RequestParseError::BadTarget => write!(f, "Failed to parse HTTP request path"),
+            RequestParseError::UriTooLong => write!(f, "HTTP request path is too long"),
+            RequestParseError::BadVersion => write!(f, "Failed to parse HTTP request protocol"),
+            RequestParseError::UnsupportedVersion => write!(f, "Unsupported HTTP request protocol"),
+            RequestParseError::BadHeaderName => write!(f, "Failed to parse HTTP request header key"),
+            RequestParseError::BadHeaderValue => write!(f, "Failed to parse HTTP request header value"),
+            RequestParseError::HeadersTooLarge => write!(f, "HTTP request headers are too large"),
+            RequestParseError::ContentTooLarge => write!(f, "HTTP request body is too large"),
+            RequestParseError::BadContentLength => write!(f, "Invalid HTTP request Content-Length"),
+            RequestParseError::BadTransferEncoding => write!(f, "Invalid HTTP request Transfer-Encoding"),
+            RequestParseError::BadChunk => write!(f, "Failed to parse HTTP request chunk"),
+            RequestParseError::Truncated => write!(f, "Connection closed before the HTTP request was complete"),
+            RequestParseError::Io(io_error) => write!(f, "HTTP request read error: {}", io_error),
+        }
+    }
+}
+
+impl std::error::Error for RequestParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RequestParseError::Io(io_error) => Some(io_error),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for RequestParseError {
+    fn from(io_error: std::io::Error) -> Self {
+        RequestParseError::Io(io_error)
+    }
+}
+
+enum RequestReadPart {
+    StartLine,
+    Headers,
+    Body,
+    ChunkSize,
+    ChunkData,
+    ChunkDataEnd,
+    Trailers,
+    Complete,
+}
+
+enum RequestBodyFraming {
+    ContentLength(usize),
+    Chunked,
+}
+
+/// The result of feeding bytes into a `RequestParser`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The request isn't complete yet; every fed byte was consumed, and the parser needs more.
+    Incomplete,
+    /// The request is complete, using this many bytes from the most recent `feed()`.
+    /// Any bytes after those belong to whatever follows the request on its transport.
+    Complete(usize),
+}
+
+/// An incremental HTTP/1.x request parser.
+///
+/// Bytes can be fed in arbitrarily-sized pieces, e.g. straight from successive `read()` calls;
+/// any partial line (start line, header, or chunk size) is carried over until the rest of it arrives.
+pub struct RequestParser {
+    limits: ParserLimits,
+    request: http::Request<Vec<u8>>,
+    read_part: RequestReadPart,
+    line_bytes: Vec<u8>,
+    headers_len: usize,
+    header_count: usize,
+    body_bytes: Vec<u8>,
+    body_remaining: usize,
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        RequestParser {
+            limits,
+            request: http::Request::default(),
+            read_part: RequestReadPart::StartLine,
+            line_bytes: Vec::new(),
+            headers_len: 0,
+            header_count: 0,
+            body_bytes: Vec::new(),
+            body_remaining: 0,
+        }
+    }
+
+    /// Parse the next piece of a request.
+    ///
+    /// Once this returns `Status::Complete`, the request is available from `take_request()`,
+    /// and further bytes won't be consumed until then.
+    /// After an error, the parser's state is unspecified, and it shouldn't be fed any more bytes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Status, RequestParseError> {
+        let mut read_pos = 0;
+        loop {
+            match self.read_part {
+                RequestReadPart::StartLine | RequestReadPart::Headers | RequestReadPart::ChunkSize | RequestReadPart::ChunkDataEnd | RequestReadPart::Trailers => {
+                    // Each of these parts is made up of whole lines, which may arrive across several pieces.
+                    let line_result = read_line_into(&bytes[read_pos..], &mut self.line_bytes);
+                    self.check_line_len()?;
+                    match line_result {
+                        Some(line_len) => {
+                            read_pos += line_len;
+
+                            let line_bytes = std::mem::take(&mut self.line_bytes);
+                            self.parse_line(trim_line_break(&line_bytes))?;
+                        },
+                        None => {
+                            return Ok(Status::Incomplete);
+                        }
+                    }
+                },
+                RequestReadPart::Body | RequestReadPart::ChunkData => {
+                    // Body data continues for exactly the expected length, which may span several pieces.
+                    let body_len = std::cmp::min(self.body_remaining, bytes.len() - read_pos);
+                    self.body_bytes.extend_from_slice(&bytes[read_pos..read_pos+body_len]);
+
+                    read_pos += body_len;
+                    self.body_remaining -= body_len;
+                    if self.body_remaining > 0 {
+                        return Ok(Status::Incomplete);
+                    }
+
+                    match self.read_part {
+                        RequestReadPart::Body => {
+                            self.complete_request();
+                        },
+                        _ => {
+                            self.read_part = RequestReadPart::ChunkDataEnd;
+                        }
+                    }
+                },
+                RequestReadPart::Complete => {
+                    return Ok(Status::Complete(read_pos));
+                }
+            }
+        }
+    }
+
+    /// Take the completed request (if there is one), and reset the parser to read another request.
+    pub fn take_request(&mut self) -> Option<http::Request<Vec<u8>>> {
+        match self.read_part {
+            RequestReadPart::Complete => {
+                let request = std::mem::take(&mut self.request);
+                *self = RequestParser::with_limits(self.limits.clone());
+                Some(request)
+            },
+            _ => None
+        }
+    }
+
+    fn check_line_len(&self) -> Result<(), RequestParseError> {
+        // Lines are checked as they accumulate, so an endless line is caught before its end ever arrives.
+        match self.read_part {
+            RequestReadPart::StartLine => {
+                // The start line allows a little extra room for the method and protocol around the target.
+                if self.line_bytes.len() > self.limits.max_uri_len + 64 {
+                    return Err(RequestParseError::UriTooLong);
+                }
+            },
+            RequestReadPart::Headers | RequestReadPart::Trailers => {
+                if self.line_bytes.len() > self.limits.max_header_line_len || self.headers_len + self.line_bytes.len() > self.limits.max_headers_len {
+                    return Err(RequestParseError::HeadersTooLarge);
+                }
+            },
+            _ => {
+                if self.line_bytes.len() > MAX_CHUNK_LINE_LEN {
+                    return Err(RequestParseError::BadChunk);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Result<(), RequestParseError> {
+        match self.read_part {
+            RequestReadPart::StartLine => {
+                // Clients may send stray line breaks ahead of a request, which should just be skipped.
+                if line.is_empty() {
+                    return Ok(());
+                }
+
+                self.parse_start_line(line)?;
+                self.read_part = RequestReadPart::Headers;
+            },
+            RequestReadPart::Headers => {
+                // If the line is empty, then we're done reading headers, and are on to the request body.
+                if line.is_empty() {
+                    return self.start_body();
+                }
+
+                self.count_header_line(line)?;
+                self.parse_header_line(line)?;
+            },
+            RequestReadPart::ChunkSize => {
+                // A chunk-size line looks like: "1a;name=value"
+                // - The chunk size in hex digits, optionally followed by ';'-separated chunk extensions,
+                // - Then a line break, after which the chunk data begins.
+                // A chunk size of zero marks the last chunk, which is followed by trailer fields.
+                let chunk_size = parse_chunk_size_line(line)?;
+                log::debug!("request chunk size {}", chunk_size);
+
+                if chunk_size > self.limits.max_body_len - self.body_bytes.len() {
+                    return Err(RequestParseError::ContentTooLarge);
+                }
+
+                if chunk_size == 0 {
+                    self.read_part = RequestReadPart::Trailers;
+                } else {
+                    self.body_remaining = chunk_size;
+                    self.read_part = RequestReadPart::ChunkData;
+                }
+            },
+            RequestReadPart::ChunkDataEnd => {
+                // Chunk data is followed by a line break, and then the next chunk-size line.
+                if !line.is_empty() {
+                    return Err(RequestParseError::BadChunk);
+                }
+
+                self.read_part = RequestReadPart::ChunkSize;
+            },
+            RequestReadPart::Trailers => {
+                // Trailer lines look like header lines, and continue until an empty line ends the request.
+                // They're not merged into the request headers, since handlers expect headers to precede the body.
+                if line.is_empty() {
+                    self.complete_request();
+                    return Ok(());
+                }
+
+                self.count_header_line(line)?;
+                log::debug!("request trailer {}", String::from_utf8_lossy(line));
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn count_header_line(&mut self, line: &[u8]) -> Result<(), RequestParseError> {
+        self.headers_len += line.len();
+        self.header_count += 1;
+        if self.header_count > self.limits.max_header_count {
+            return Err(RequestParseError::HeadersTooLarge);
+        }
+
+        Ok(())
+    }
+
+    fn parse_start_line(&mut self, line: &[u8]) -> Result<(), RequestParseError> {
+        // A start line looks like: "GET /path HTTP/1.1"
+        // - The request method (verb) followed by spaces,
+        // - Then a request path followed by spaces,
+        // - Then a protocol name and version specifier.
+        let mut line_parts = line.split(|&b| b == b' ').filter(|part| !part.is_empty());
+        let (Some(method_bytes), Some(path_bytes), Some(protocol_bytes), None) = (line_parts.next(), line_parts.next(), line_parts.next(), line_parts.next()) else {
+            return Err(RequestParseError::BadStartLine);
+        };
+
+        // Parse the method string into an http::Method value.
+        log::debug!("request method {}", String::from_utf8_lossy(method_bytes));
+        match http::Method::from_bytes(method_bytes) {
+            Ok(method) => {
+                *self.request.method_mut() = method;
+            },
+            Err(_) => {
+                return Err(RequestParseError::BadMethod);
+            }
+        }
+
+        // Parse the request path, which is usually just a path and query (e.g. "/search?q=abc"),
+        // but may also be an absolute URI, an authority for CONNECT, or "*" for OPTIONS.
+        log::debug!("request path {}", String::from_utf8_lossy(path_bytes));
+        if path_bytes.len() > self.limits.max_uri_len {
+            return Err(RequestParseError::UriTooLong);
+        }
+
+        let method = self.request.method();
+        let path_form_ok = match path_bytes {
+            [b'/', ..] => true,
+            b"*" => method == http::Method::OPTIONS,
+            _ if method == http::Method::CONNECT => true,
+            _ => path_bytes.windows(3).any(|window| window == b"://")
+        };
+        if !path_form_ok {
+            return Err(RequestParseError::BadTarget);
+        }
+        match http::Uri::try_from(path_bytes) {
+            Ok(uri) => {
+                *self.request.uri_mut() = uri;
+            },
+            Err(_) => {
+                return Err(RequestParseError::BadTarget);
+            }
+        }
+
+        // Parse the protocol string into an http::Version value.
+        // Any later HTTP/1 minor version is read as HTTP/1.1, which is the highest this server speaks.
+        log::debug!("request protocol {}", String::from_utf8_lossy(protocol_bytes));
+        match protocol_bytes {
+            [b'H', b'T', b'T', b'P', b'/', major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                match (major, minor) {
+                    (b'1', b'0') => {
+                        *self.request.version_mut() = http::Version::HTTP_10;
+                    },
+                    (b'1', _) => {
+                        *self.request.version_mut() = http::Version::HTTP_11;
+                    },
+                    _ => {
+                        return Err(RequestParseError::UnsupportedVersion);
+                    }
+                }
+            },
+            _ => {
+                return Err(RequestParseError::BadVersion);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_header_line(&mut self, line: &[u8]) -> Result<(), RequestParseError> {
+        // A header line looks like: "content-type: text/something;extrabits"
+        // - The header key (name) followed by a colon and spaces,
+        // - Then the header value.
+        let Some(key_len) = line.iter().position(|&b| b == b':') else {
+            return Err(RequestParseError::BadHeaderName);
+        };
+
+        // Parse the header key.
+        let key_bytes = &line[..key_len];
+        log::debug!("request header key {}", String::from_utf8_lossy(key_bytes));
+
+        let Ok(header_name) = http::HeaderName::try_from(key_bytes) else {
+            return Err(RequestParseError::BadHeaderName);
+        };
+
+        // Parse the header value, without its surrounding whitespace.
+        let value_bytes = line[key_len+1..].trim_ascii();
+        log::debug!("request header value {}", String::from_utf8_lossy(value_bytes));
+
+        let Ok(header_value) = http::HeaderValue::try_from(value_bytes) else {
+            return Err(RequestParseError::BadHeaderValue);
+        };
+        self.request.headers_mut().append(header_name, header_value);
+
+        Ok(())
+    }
+
+    fn start_body(&mut self) -> Result<(), RequestParseError> {
+        // The body is framed by either chunked Transfer-Encoding or the Content-Length header;
+        // without either one, there's no body at all.
+        match read_body_framing(self.request.headers())? {
+            RequestBodyFraming::Chunked => {
+                self.read_part = RequestReadPart::ChunkSize;
+            },
+            RequestBodyFraming::ContentLength(0) => {
+                self.complete_request();
+            },
+            RequestBodyFraming::ContentLength(content_length) => {
+                // A body that's too large is rejected up-front, rather than after reading it all.
+                if content_length > self.limits.max_body_len {
+                    return Err(RequestParseError::ContentTooLarge);
+                }
+
+                self.body_remaining = content_length;
+                self.read_part = RequestReadPart::Body;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn complete_request(&mut self) {
+        *self.request.body_mut() = std::mem::take(&mut self.body_bytes);
+        self.read_part = RequestReadPart::Complete;
+    }
+}
+
+/// Read one request from a buffered transport, e.g. a `BufReader` around a socket, or a plain `&[u8]`.
+///
+/// Only the bytes belonging to this request are consumed; anything after it stays in the reader's buffer,
+/// so calling this again with the same reader reads the next request pipelined behind this one.
+/// The reader blocks for as long as its transport does, so any read timeout is up to the caller to set.
+pub fn read_http_request(request_reader: &mut impl std::io::BufRead, limits: &ParserLimits) -> Result<http::Request<Vec<u8>>, RequestParseError> {
+    let mut request_parser = RequestParser::with_limits(limits.clone());
+    loop {
+        let read_result = request_reader.fill_buf();
+        match read_result {
+            Ok(read_bytes) => {
+                if read_bytes.is_empty() {
+                    // The peer closed the stream before the request was complete.
+                    return Err(RequestParseError::Truncated);
+                }
+
+                let read_len = read_bytes.len();
+                match request_parser.feed(read_bytes)? {
+                    Status::Complete(used_len) => {
+                        request_reader.consume(used_len);
+                        break;
+                    },
+                    Status::Incomplete => {
+                        request_reader.consume(read_len);
+                    }
+                }
+            },
+            Err(readstream_error) => {
+                if readstream_error.kind() == std::io::ErrorKind::Interrupted {
+                    // The read was interrupted before any data arrived; just try again.
+                    continue;
+                }
+
+                return Err(RequestParseError::Io(readstream_error));
+            }
+        }
+    }
+
+    request_parser.take_request().ok_or(RequestParseError::Truncated)
+}
+
+fn read_body_framing(headers: &http::HeaderMap) -> Result<RequestBodyFraming, RequestParseError> {
+    let mut transfer_codings: Vec<String> = Vec::new();
+    for header_value in headers.get_all(http::header::TRANSFER_ENCODING) {
+        let Ok(value_str) = header_value.to_str() else {
+            return Err(RequestParseError::BadTransferEncoding);
+        };
+        for transfer_coding in value_str.split(',') {
+            let transfer_coding = transfer_coding.trim();
+            if !transfer_coding.is_empty() {
+                transfer_codings.push(transfer_coding.to_ascii_lowercase());
+            }
+        }
+    }
+
+    if transfer_codings.is_empty() {
+        return Ok(RequestBodyFraming::ContentLength(read_content_length(headers)?));
+    }
+
+    // A request with Transfer-Encoding is only framed correctly if chunked is the final coding,
+    // and a Content-Length alongside it is a sign of request smuggling rather than an honest client.
+    if transfer_codings.last().map(|coding| coding.as_str()) != Some("chunked") {
+        return Err(RequestParseError::BadTransferEncoding);
+    }
+    if headers.contains_key(http::header::CONTENT_LENGTH) {
+        return Err(RequestParseError::BadTransferEncoding);
+    }
+
+    Ok(RequestBodyFraming::Chunked)
+}
+
+fn read_content_length(headers: &http::HeaderMap) -> Result<usize, RequestParseError> {
+    let mut content_length: Option<usize> = None;
+
+    // Multiple Content-Length headers are only acceptable if they all agree.
+    for header_value in headers.get_all(http::header::CONTENT_LENGTH) {
+        let parsed_length = header_value.to_str().ok()
+            .filter(|value_str| !value_str.is_empty() && value_str.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|value_str| value_str.parse::<usize>().ok());
+        match (parsed_length, content_length) {
+            (Some(length), None) => {
+                content_length = Some(length);
+            },
+            (Some(length), Some(previous_length)) if length == previous_length => {},
+            _ => {
+                return Err(RequestParseError::BadContentLength);
+            }
+        }
+    }
+
+    Ok(content_length.unwrap_or(0))
+}
+
+fn read_line_into(bytes: &[u8], line: &mut Vec<u8>) -> Option<usize> {
+    // Append bytes up to (and including) the next '\n' onto the line, returning how many were used
+    // if the line is now complete.
+    match bytes.iter().position(|&b| b == b'\n') {
+        Some(line_end) => {
+            line.extend_from_slice(&bytes[..line_end+1]);
+            Some(line_end + 1)
+        },
+        None => {
+            line.extend_from_slice(bytes);
+            None
+        }
+    }
+}
+
+fn trim_line_break(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn parse_chunk_size_line(line: &[u8]) -> Result<usize, RequestParseError> {
+    // Chunk extensions aren't meaningful to this server, so everything after the first ';' is skipped.
+    let size_end = line.iter().position(|&b| b == b';').unwrap_or(line.len());
+    let size_bytes = line[..size_end].trim_ascii_end();
+    if size_bytes.is_empty() || !size_bytes.iter().all(|b| b.is_ascii_hexdigit()) {
+        return Err(RequestParseError::BadChunk);
+    }
+
+    let mut chunk_size: usize = 0;
+    for &size_byte in size_bytes {
+        let digit = (size_byte as char).to_digit(16).unwrap_or(0) as usize;
+        match chunk_size.checked_mul(16).and_then(|size| size.checked_add(digit)) {
+            Some(size) => {
+                chunk_size = size;
+            },
+            None => {
+                return Err(RequestParseError::BadChunk);
+            }
+        }
+    }
+
+    Ok(chunk_size)
+}
+
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    fn parse_in_pieces(request_bytes: &[u8], piece_len: usize, limits: super::ParserLimits) -> Result<(http::Request<Vec<u8>>, usize), super::RequestParseError> {
+        let mut request_parser = super::RequestParser::with_limits(limits);
+        let mut read_pos = 0;
+        for piece in request_bytes.chunks(piece_len) {
+            match request_parser.feed(piece)? {
+                super::Status::Complete(used_len) => {
+                    let request = request_parser.take_request().expect("a completed request");
+                    return Ok((request, read_pos + used_len));
+                },
+                super::Status::Incomplete => {
+                    read_pos += piece.len();
+                }
+            }
+        }
+        panic!("request wasn't complete after {} bytes", read_pos);
+    }
+
+    fn parse(request_bytes: &[u8]) -> Result<http::Request<Vec<u8>>, super::RequestParseError> {
+        parse_in_pieces(request_bytes, request_bytes.len(), super::ParserLimits::default()).map(|(request, _)| request)
+    }
+
+    fn parse_error_status(request_bytes: &[u8], limits: super::ParserLimits) -> http::StatusCode {
+        match parse_in_pieces(request_bytes, request_bytes.len(), limits) {
+            Ok(_) => panic!("request parsed, but should have failed"),
+            Err(parse_error) => parse_error.status(),
+        }
+    }
+
+    #[test]
+    fn reads_requests_from_byte_slices() {
+        let limits = super::ParserLimits::default();
+        let mut request_reader: &[u8] = b"POST /submit HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let request = super::read_http_request(&mut request_reader, &limits).unwrap();
+        assert_eq!(request.method(), http::Method::POST);
+        assert_eq!(request.uri(), "/submit");
+        assert_eq!(request.body(), b"hello");
+        assert!(request_reader.is_empty());
+
+        let mut truncated_reader: &[u8] = b"POST /submit HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel";
+        assert!(matches!(super::read_http_request(&mut truncated_reader, &limits), Err(super::RequestParseError::Truncated)));
+    }
+
+    #[test]
+    fn parses_request_split_at_every_byte() {
+        let request_bytes = b"POST /submit?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+        for piece_len in 1..request_bytes.len() {
+            let (request, used_len) = parse_in_pieces(request_bytes, piece_len, super::ParserLimits::default()).unwrap();
+            assert_eq!(request.method(), http::Method::POST);
+            assert_eq!(request.uri(), "/submit?x=1");
+            assert_eq!(request.version(), http::Version::HTTP_11);
+            assert_eq!(request.headers()[http::header::HOST], "example.com");
+            assert_eq!(request.body(), b"hello");
+            assert_eq!(used_len, request_bytes.len());
+        }
+    }
+
+    #[test]
+    fn decodes_chunked_body_with_extensions_and_trailers() {
+        let request_bytes = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;name=value\r\nhello\r\n6 ; x\r\n world\r\n0\r\nX-Trailer: yes\r\n\r\n";
+        for piece_len in [1, 3, request_bytes.len()] {
+            let (request, _) = parse_in_pieces(request_bytes, piece_len, super::ParserLimits::default()).unwrap();
+            assert_eq!(request.body(), b"hello world");
+            assert!(!request.headers().contains_key("x-trailer"));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_chunks() {
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"), Err(super::RequestParseError::BadChunk)));
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n"), Err(super::RequestParseError::BadChunk)));
+    }
+
+    #[test]
+    fn rejects_ambiguous_body_framing() {
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!"), Err(super::RequestParseError::BadContentLength)));
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\nhello"), Err(super::RequestParseError::BadContentLength)));
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n0\r\n\r\n"), Err(super::RequestParseError::BadTransferEncoding)));
+        assert!(matches!(parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n"), Err(super::RequestParseError::BadTransferEncoding)));
+
+        let request = parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(request.body(), b"hello");
+    }
+
+    #[test]
+    fn rejects_bad_start_lines() {
+        assert!(matches!(parse(b"GET /\r\n\r\n"), Err(super::RequestParseError::BadStartLine)));
+        assert!(matches!(parse(b"GET nope HTTP/1.1\r\n\r\n"), Err(super::RequestParseError::BadTarget)));
+        assert!(matches!(parse(b"GET / HTTP/2.0\r\n\r\n"), Err(super::RequestParseError::UnsupportedVersion)));
+        assert!(matches!(parse(b"GET / HTTX/1.1\r\n\r\n"), Err(super::RequestParseError::BadVersion)));
+        assert!(matches!(parse(b"GET / HTTP/1.1\r\nNo colon\r\n\r\n"), Err(super::RequestParseError::BadHeaderName)));
+    }
+
+    #[test]
+    fn enforces_size_limits() {
+        let limits = super::ParserLimits {
+            max_uri_len: 16,
+            max_header_count: 2,
+            max_header_line_len: 32,
+            max_headers_len: 48,
+            max_body_len: 4,
+        };
+
+        assert_eq!(parse_error_status(b"GET /aaaaaaaaaaaaaaaaaaaaaaaa HTTP/1.1\r\n\r\n", limits.clone()), http::StatusCode::URI_TOO_LONG);
+        assert_eq!(parse_error_status(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n", limits.clone()), http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        assert_eq!(parse_error_status(b"GET / HTTP/1.1\r\nX-Long: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n", limits.clone()), http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        assert_eq!(parse_error_status(b"GET / HTTP/1.1\r\nX-One: aaaaaaaaaaaaaaaaaaaa\r\nX-Two: aaaaaaaaaaaaaaaaaaaa\r\n\r\n", limits.clone()), http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        assert_eq!(parse_error_status(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello", limits.clone()), http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(parse_error_status(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n", limits.clone()), http::StatusCode::PAYLOAD_TOO_LARGE);
+
+        // An endless line is rejected as soon as it's too long, without waiting for its end.
+        let mut request_parser = super::RequestParser::with_limits(limits);
+        assert!(matches!(request_parser.feed(&[b'a'; 100]), Err(super::RequestParseError::UriTooLong)));
+    }
+
+    #[test]
+    fn leaves_pipelined_bytes_unconsumed() {
+        let request_bytes = b"GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n";
+        let (first_request, used_len) = parse_in_pieces(request_bytes, request_bytes.len(), super::ParserLimits::default()).unwrap();
+        assert_eq!(first_request.uri(), "/first");
+        assert_eq!(&request_bytes[used_len..], b"GET /second HTTP/1.1\r\n\r\n");
+
+        let mut request_reader: &[u8] = request_bytes;
+        let limits = super::ParserLimits::default();
+        assert_eq!(super::read_http_request(&mut request_reader, &limits).unwrap().uri(), "/first");
+        assert_eq!(super::read_http_request(&mut request_reader, &limits).unwrap().uri(), "/second");
+        assert!(matches!(super::read_http_request(&mut request_reader, &limits), Err(super::RequestParseError::Truncated)));
+    }
+}
@@ -0,0 +1,334 @@
+const MAX_UPSTREAM_HEADER_LINE_LEN: u64 = 8 * 1024;
+const MAX_UPSTREAM_HEADER_COUNT: usize = 100;
+const X_FORWARDED_HOST: http::HeaderName = http::HeaderName::from_static("x-forwarded-host");
+
+/// Forwards requests to an upstream HTTP server, e.g. `Router::new().any("/api/*rest", Proxy::new("http://127.0.0.1:9000")?)`.
+///
+/// The request's whole path (and query) is forwarded, after the upstream URL's own path if it has one,
+/// so with "http://127.0.0.1:9000/v2" a request for "/api/users" goes upstream as "/v2/api/users".
+///
+/// Each request gets its own upstream connection, and is sent as HTTP/1.0 so that the response is framed
+/// by Content-Length or the end of the connection. An unreachable or misbehaving upstream is answered with 502,
+/// and one that doesn't answer in time with 504.
+pub struct Proxy {
+    upstream_authority: http::uri::Authority,
+    path_prefix: String,
+    timeout: std::time::Duration,
+    max_response_len: usize,
+}
+
+impl Proxy {
+    pub fn new(upstream: &str) -> Result<Self, std::io::Error> {
+        let invalid_upstream = |reason: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid upstream {}: {}", upstream, reason));
+
+        let upstream_uri: http::Uri = upstream.parse().map_err(|uri_error: http::uri::InvalidUri| invalid_upstream(&uri_error.to_string()))?;
+        if upstream_uri.scheme_str() != Some("http") {
+            return Err(invalid_upstream("only http:// upstreams are supported"));
+        }
+        let Some(upstream_authority) = upstream_uri.authority() else {
+            return Err(invalid_upstream("missing a host"));
+        };
+        if upstream_uri.query().is_some() {
+            return Err(invalid_upstream("upstreams can't have a query"));
+        }
+
+        Ok(Proxy {
+            upstream_authority: upstream_authority.clone(),
+            path_prefix: upstream_uri.path().trim_end_matches('/').to_string(),
+            timeout: std::time::Duration::from_secs(30),
+            max_response_len: 16 * 1024 * 1024,
+        })
+    }
+
+    /// How long connecting to the upstream, and each read from or write to it, may take.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The largest response body that the upstream can send back, beyond which the request fails with 502.
+    pub fn max_response_len(mut self, max_response_len: usize) -> Self {
+        self.max_response_len = max_response_len;
+        self
+    }
+
+    fn connect(&self) -> Result<std::net::TcpStream, std::io::Error> {
+        let upstream_port = self.upstream_authority.port_u16().unwrap_or(80);
+        let upstream_addrs = std::net::ToSocketAddrs::to_socket_addrs(&(self.upstream_authority.host(), upstream_port))?;
+
+        let mut connect_error = std::io::Error::new(std::io::ErrorKind::NotFound, "Upstream host has no addresses");
+        for upstream_addr in upstream_addrs {
+            match std::net::TcpStream::connect_timeout(&upstream_addr, self.timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(addr_error) => connect_error = addr_error,
+            }
+        }
+        Err(connect_error)
+    }
+
+    fn forward(&self, request: &crate::Request) -> Result<crate::Response, std::io::Error> {
+        let stream = self.connect()?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        self.write_request(&mut std::io::BufWriter::new(&stream), request)?;
+
+        let mut response_reader = std::io::BufReader::new(&stream);
+        self.read_response(&mut response_reader, request.method() == http::Method::HEAD)
+    }
+
+    fn write_request(&self, request_writer: &mut impl std::io::Write, request: &crate::Request) -> Result<(), std::io::Error> {
+        let path_and_query = request.uri().path_and_query().map_or("/", |path_and_query| path_and_query.as_str());
+        let upstream_target = if path_and_query.starts_with('/') {
+            format!("{}{}", self.path_prefix, path_and_query)
+        } else {
+            path_and_query.to_string()
+        };
+        write!(request_writer, "{} {} HTTP/1.0\r\n", request.method(), upstream_target)?;
+        write!(request_writer, "{}: {}\r\n", http::header::HOST, self.upstream_authority)?;
+
+        let connection_headers = connection_header_names(request.headers());
+        for (header_name, header_value) in request.headers() {
+            // A client's own X-Forwarded-Host can't be trusted, so it's replaced by the Host it actually sent.
+            if header_name == http::header::HOST || header_name == http::header::CONTENT_LENGTH || header_name == X_FORWARDED_HOST || is_hop_by_hop(header_name, &connection_headers) {
+                continue;
+            }
+            write_header_line(request_writer, header_name, header_value)?;
+        }
+        if let Some(request_host) = request.headers().get(http::header::HOST) {
+            write_header_line(request_writer, &X_FORWARDED_HOST, request_host)?;
+        }
+        if !request.body().is_empty() {
+            write!(request_writer, "{}: {}\r\n", http::header::CONTENT_LENGTH, request.body().len())?;
+        }
+        request_writer.write_all(b"\r\n")?;
+        request_writer.write_all(request.body())?;
+        request_writer.flush()
+    }
+
+    fn read_response(&self, response_reader: &mut impl std::io::BufRead, is_head: bool) -> Result<crate::Response, std::io::Error> {
+        // A status line looks like: "HTTP/1.0 404 Not Found"
+        let status_line = read_upstream_line(response_reader)?;
+        let mut status_parts = status_line.splitn(3, |line_byte| *line_byte == b' ');
+        let status_protocol = status_parts.next().unwrap_or(b"");
+        let status_code = status_parts.next().unwrap_or(b"");
+        if !status_protocol.starts_with(b"HTTP/1.") {
+            return Err(invalid_upstream_response("bad status line"));
+        }
+        let status = http::StatusCode::from_bytes(status_code).map_err(|_| invalid_upstream_response("bad status code"))?;
+
+        let mut response = crate::Response::default();
+        *response.status_mut() = status;
+
+        let mut header_count = 0;
+        loop {
+            let header_line = read_upstream_line(response_reader)?;
+            if header_line.is_empty() {
+                break;
+            }
+
+            header_count += 1;
+            if header_count > MAX_UPSTREAM_HEADER_COUNT {
+                return Err(invalid_upstream_response("too many headers"));
+            }
+
+            let Some(colon_pos) = header_line.iter().position(|line_byte| *line_byte == b':') else {
+                return Err(invalid_upstream_response("bad header line"));
+            };
+            let header_name = http::HeaderName::from_bytes(&header_line[..colon_pos]).map_err(|_| invalid_upstream_response("bad header name"))?;
+            let header_value = http::HeaderValue::from_bytes(header_line[colon_pos+1..].trim_ascii()).map_err(|_| invalid_upstream_response("bad header value"))?;
+            response.headers_mut().append(header_name, header_value);
+        }
+
+        let connection_headers = connection_header_names(response.headers());
+        if response.headers().contains_key(http::header::TRANSFER_ENCODING) {
+            // An HTTP/1.0 request shouldn't get a chunked response, and this doesn't try to decode one.
+            return Err(invalid_upstream_response("unexpected Transfer-Encoding"));
+        }
+        let hop_by_hop_headers: Vec<http::HeaderName> = response.headers().keys()
+            .filter(|header_name| is_hop_by_hop(header_name, &connection_headers))
+            .cloned()
+            .collect();
+        for header_name in hop_by_hop_headers {
+            response.headers_mut().remove(header_name);
+        }
+
+        // Content-Length is left on the response; the response writer replaces it, except for HEAD responses,
+        // where it describes the body the upstream didn't send.
+        let body_allowed = !(is_head || status.is_informational() || status == http::StatusCode::NO_CONTENT || status == http::StatusCode::NOT_MODIFIED);
+        if body_allowed {
+            let content_length = match response.headers().get(http::header::CONTENT_LENGTH) {
+                Some(length_value) => {
+                    let length_str = length_value.to_str().map_err(|_| invalid_upstream_response("bad Content-Length"))?;
+                    Some(length_str.trim().parse::<usize>().map_err(|_| invalid_upstream_response("bad Content-Length"))?)
+                },
+                None => None,
+            };
+
+            let body = response.body_mut();
+            match content_length {
+                Some(content_length) if content_length > self.max_response_len => {
+                    return Err(invalid_upstream_response("response body too large"));
+                },
+                Some(content_length) => {
+                    body.resize(content_length, 0);
+                    response_reader.read_exact(body)?;
+                },
+                None => {
+                    // Without a Content-Length, the body runs until the upstream closes the connection.
+                    let max_read_len = self.max_response_len as u64 + 1;
+                    std::io::Read::read_to_end(&mut std::io::Read::take(&mut *response_reader, max_read_len), body)?;
+                    if body.len() > self.max_response_len {
+                        return Err(invalid_upstream_response("response body too large"));
+                    }
+                }
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+impl crate::Handler for Proxy {
+    fn call(&self, request: crate::Request) -> crate::Response {
+        match self.forward(&request) {
+            Ok(response) => response,
+            Err(upstream_error) => {
+                log::error!("Upstream {} error: {}", self.upstream_authority, upstream_error);
+                let status = match upstream_error.kind() {
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => http::StatusCode::GATEWAY_TIMEOUT,
+                    _ => http::StatusCode::BAD_GATEWAY,
+                };
+                crate::create_text_response(status, &format!("Upstream error for path {}", request.uri().path()))
+            }
+        }
+    }
+}
+
+fn invalid_upstream_response(reason: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid upstream response, {}", reason))
+}
+
+fn read_upstream_line(response_reader: &mut impl std::io::BufRead) -> Result<Vec<u8>, std::io::Error> {
+    let mut line = Vec::new();
+    std::io::BufRead::read_until(&mut std::io::Read::take(&mut *response_reader, MAX_UPSTREAM_HEADER_LINE_LEN), b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(match line.len() as u64 {
+            MAX_UPSTREAM_HEADER_LINE_LEN => invalid_upstream_response("header line too long"),
+            _ => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Upstream closed the connection"),
+        });
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(line)
+}
+
+fn write_header_line(request_writer: &mut impl std::io::Write, header_name: &http::HeaderName, header_value: &http::HeaderValue) -> Result<(), std::io::Error> {
+    request_writer.write_all(header_name.as_str().as_bytes())?;
+    request_writer.write_all(b": ")?;
+    request_writer.write_all(header_value.as_bytes())?;
+    request_writer.write_all(b"\r\n")
+}
+
+fn connection_header_names(headers: &http::HeaderMap) -> Vec<String> {
+    // The Connection header can name more headers that only apply to this hop, e.g. "Connection: close, X-Hop-Secret".
+    headers.get_all(http::header::CONNECTION).iter()
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|value_str| value_str.split(','))
+        .map(|connection_option| connection_option.trim().to_ascii_lowercase())
+        .filter(|connection_option| !connection_option.is_empty())
+        .collect()
+}
+
+fn is_hop_by_hop(header_name: &http::HeaderName, connection_headers: &[String]) -> bool {
+    // These describe a single connection, rather than the message, so they aren't forwarded in either direction.
+    const HOP_BY_HOP_HEADERS: [&str; 9] = [
+        "connection", "keep-alive", "proxy-connection", "proxy-authenticate", "proxy-authorization", "te", "trailer", "transfer-encoding", "upgrade",
+    ];
+
+    HOP_BY_HOP_HEADERS.contains(&header_name.as_str()) || connection_headers.iter().any(|connection_header| connection_header == header_name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    fn proxy() -> super::Proxy {
+        super::Proxy::new("http://127.0.0.1:9000/v2").unwrap().max_response_len(10)
+    }
+
+    fn read_fixture(fixture: &[u8], is_head: bool) -> Result<crate::Response, std::io::Error> {
+        proxy().read_response(&mut &fixture[..], is_head)
+    }
+
+    #[test]
+    fn rewrites_forwarded_requests() {
+        let request = http::Request::builder()
+            .method(http::Method::POST)
+            .uri("/api/users?page=2")
+            .header(http::header::HOST, "example.com")
+            .header("x-forwarded-host", "spoofed.example")
+            .header(http::header::CONNECTION, "keep-alive, X-Hop")
+            .header("x-hop", "1")
+            .header(http::header::CONTENT_LENGTH, "999")
+            .header(http::header::ACCEPT, "*/*")
+            .body(b"hello".to_vec())
+            .unwrap();
+
+        let mut request_bytes = Vec::new();
+        proxy().write_request(&mut request_bytes, &request).unwrap();
+        assert_eq!(String::from_utf8(request_bytes).unwrap(), "POST /v2/api/users?page=2 HTTP/1.0\r\nhost: 127.0.0.1:9000\r\naccept: */*\r\nx-forwarded-host: example.com\r\ncontent-length: 5\r\n\r\nhello");
+    }
+
+    #[test]
+    fn frames_responses_by_content_length() {
+        let response = read_fixture(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello, and more", false).unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.body(), b"hello");
+
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhel", false).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\nContent-Length: five\r\n\r\n", false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn frames_responses_by_end_of_connection() {
+        let response = read_fixture(b"HTTP/1.0 404 Not Found\r\n\r\nnot here", false).unwrap();
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(response.body(), b"not here");
+    }
+
+    #[test]
+    fn enforces_max_response_len() {
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\n0123456789", false).unwrap().body(), b"0123456789");
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\nContent-Length: 11\r\n\r\n0123456789a", false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\n\r\n0123456789", false).unwrap().body(), b"0123456789");
+        assert_eq!(read_fixture(b"HTTP/1.0 200 OK\r\n\r\n0123456789a", false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_transfer_encoding() {
+        let upstream_error = read_fixture(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n", false).unwrap_err();
+        assert_eq!(upstream_error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(upstream_error.to_string(), "Invalid upstream response, unexpected Transfer-Encoding");
+    }
+
+    #[test]
+    fn strips_hop_by_hop_headers() {
+        let response = read_fixture(b"HTTP/1.1 200 OK\r\nConnection: close, X-Hop\r\nKeep-Alive: timeout=5\r\nX-Hop: 1\r\nX-Kept: 2\r\nContent-Length: 0\r\n\r\n", false).unwrap();
+        assert_eq!(response.headers().len(), 2);
+        assert_eq!(response.headers()["x-kept"], "2");
+        assert_eq!(response.headers()[http::header::CONTENT_LENGTH], "0");
+    }
+
+    #[test]
+    fn reads_no_body_for_head() {
+        let response = read_fixture(b"HTTP/1.0 200 OK\r\nContent-Length: 100\r\n\r\n", true).unwrap();
+        assert!(response.body().is_empty());
+        // Kept so that the HEAD response describes the body a GET would get.
+        assert_eq!(response.headers()[http::header::CONTENT_LENGTH], "100");
+
+        let response = read_fixture(b"HTTP/1.0 304 Not Modified\r\n\r\nignored", false).unwrap();
+        assert!(response.body().is_empty());
+    }
+}
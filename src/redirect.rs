@@ -0,0 +1,41 @@
+/// Answers every request with a redirect to the same location, e.g. `Router::new().any("/old", Redirect::to("/new"))`.
+pub struct Redirect {
+    status: http::StatusCode,
+    location: http::HeaderValue,
+}
+
+impl Redirect {
+    /// A 302 Found redirect.
+    ///
+    /// # Panics
+    /// If the location isn't a valid header value, e.g. it has a line break.
+    pub fn to(location: &str) -> Self {
+        Redirect {
+            status: http::StatusCode::FOUND,
+            location: http::HeaderValue::from_str(location).unwrap(),
+        }
+    }
+
+    /// A 308 Permanent Redirect, which clients can remember, and which keeps the request's method and body.
+    ///
+    /// # Panics
+    /// If the location isn't a valid header value, e.g. it has a line break.
+    pub fn permanent(location: &str) -> Self {
+        Redirect::to(location).status(http::StatusCode::PERMANENT_REDIRECT)
+    }
+
+    /// The redirect's status, e.g. 301, 302, 303, 307 or 308.
+    pub fn status(mut self, status: http::StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl crate::Handler for Redirect {
+    fn call(&self, _request: crate::Request) -> crate::Response {
+        let location_text = String::from_utf8_lossy(self.location.as_bytes());
+        let mut response = crate::create_text_response(self.status, &format!("Moved to {}", location_text));
+        response.headers_mut().insert(http::header::LOCATION, self.location.clone());
+        response
+    }
+}
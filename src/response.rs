@@ -0,0 +1,140 @@
+pub fn create_text_response(status: http::StatusCode, text: &str) -> http::Response<Vec<u8>> {
+    create_bytes_response(status, http::HeaderValue::from_static("text/plain"), text.as_bytes())
+}
+
+pub fn create_bytes_response(status: http::StatusCode, content_type: http::HeaderValue, bytes: &[u8]) -> http::Response<Vec<u8>> {
+    let mut response: http::Response<Vec<u8>> = http::Response::default();
+
+    // Content-Length isn't set here; write_http_response() computes it from whatever the body ends up being.
+    *response.status_mut() = status;
+    response.headers_mut().append(http::header::CONTENT_TYPE, content_type);
+    response.body_mut().extend_from_slice(bytes);
+
+    response
+}
+
+/// The protocol string for a response's status line.
+/// This server only speaks HTTP/1.x, so anything newer is answered as HTTP/1.1.
+fn http_version_str(version: http::Version) -> &'static str {
+    match version {
+        http::Version::HTTP_09 | http::Version::HTTP_10 => "HTTP/1.0",
+        _ => "HTTP/1.1"
+    }
+}
+
+/// Write a response to a transport as HTTP/1.x, using the response's own version.
+///
+/// The body is framed by a Content-Length computed from the body itself (replacing any set by the handler),
+/// unless the handler set `Transfer-Encoding: chunked` on an HTTP/1.1 response, in which case the body is written as chunks instead.
+/// Any other Transfer-Encoding (or any at all, for HTTP/1.0, which doesn't have it) is left out, so a body is never framed both ways.
+///
+/// Buffered writers aren't flushed, so that several responses can be written out together;
+/// that's up to the caller once it's done writing.
+pub fn write_http_response<B: AsRef<[u8]>>(response_writer: &mut impl std::io::Write, response: &http::Response<B>) -> Result<(), std::io::Error> {
+    write_response_parts(response_writer, response, true)
+}
+
+/// Write a response to a `HEAD` request: the same status line and headers as write_http_response(), but no body.
+///
+/// Content-Length still describes the body that a `GET` would have gotten; if the body is empty,
+/// a Content-Length set by the handler (e.g. an explicit `HEAD` route that knows the size) is kept instead.
+pub fn write_http_head_response<B: AsRef<[u8]>>(response_writer: &mut impl std::io::Write, response: &http::Response<B>) -> Result<(), std::io::Error> {
+    write_response_parts(response_writer, response, false)
+}
+
+fn write_response_parts<B: AsRef<[u8]>>(response_writer: &mut impl std::io::Write, response: &http::Response<B>, include_body: bool) -> Result<(), std::io::Error> {
+    // A status line looks like: "HTTP/1.1 404 Not Found"
+    // - The protocol name and version, followed by a space,
+    // - Then the status code, followed by a space,
+    // - Then the status's reason phrase (which may be empty), followed by a line break.
+    let http_version_string = http_version_str(response.version());
+    let reason_string = response.status().canonical_reason().unwrap_or("");
+    write!(response_writer, "{} {} {}\r\n", http_version_string, response.status().as_str(), reason_string)?;
+
+    // Statuses that never carry content get neither a body nor any framing for one.
+    let body_bytes = response.body().as_ref();
+    let status = response.status();
+    let body_allowed = !(status.is_informational() || status == http::StatusCode::NO_CONTENT || status == http::StatusCode::NOT_MODIFIED);
+    let body_chunked = body_allowed && response.version() > http::Version::HTTP_10 && response_is_chunked(response.headers());
+    let handler_content_length = response.headers().get(http::header::CONTENT_LENGTH)
+        .filter(|_| !include_body && body_bytes.is_empty());
+
+    // Header values aren't necessarily UTF-8, so they're written out as-is.
+    for (header_name, header_value) in response.headers() {
+        if header_name == http::header::CONTENT_LENGTH || (header_name == http::header::TRANSFER_ENCODING && !body_chunked) {
+            continue;
+        }
+
+        response_writer.write_all(header_name.as_str().as_bytes())?;
+        response_writer.write_all(b": ")?;
+        response_writer.write_all(header_value.as_bytes())?;
+        response_writer.write_all(b"\r\n")?;
+    }
+    if body_allowed && !body_chunked {
+        match handler_content_length {
+            Some(content_length) => {
+                write!(response_writer, "{}: ", http::header::CONTENT_LENGTH)?;
+                response_writer.write_all(content_length.as_bytes())?;
+                response_writer.write_all(b"\r\n")?;
+            },
+            None => write!(response_writer, "{}: {}\r\n", http::header::CONTENT_LENGTH, body_bytes.len())?
+        }
+    }
+    response_writer.write_all(b"\r\n")?;
+
+    if !body_allowed || !include_body {
+        return Ok(());
+    }
+
+    if body_chunked {
+        // The whole body is already in hand, so it goes out as one chunk, followed by the last (empty) chunk.
+        if !body_bytes.is_empty() {
+            write!(response_writer, "{:x}\r\n", body_bytes.len())?;
+            response_writer.write_all(body_bytes)?;
+            response_writer.write_all(b"\r\n")?;
+        }
+        response_writer.write_all(b"0\r\n\r\n")?;
+    } else {
+        response_writer.write_all(body_bytes)?;
+    }
+
+    Ok(())
+}
+
+fn response_is_chunked(headers: &http::HeaderMap) -> bool {
+    // Only the final transfer coding determines how the body is framed.
+    let final_transfer_coding = headers.get_all(http::header::TRANSFER_ENCODING).iter()
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|value_str| value_str.split(','))
+        .map(|transfer_coding| transfer_coding.trim())
+        .rfind(|transfer_coding| !transfer_coding.is_empty());
+
+    final_transfer_coding.is_some_and(|transfer_coding| transfer_coding.eq_ignore_ascii_case("chunked"))
+}
+
+#[cfg(test)]
+mod tests {
+    fn write_with_transfer_encoding(version: http::Version, transfer_encoding: &str) -> String {
+        let mut response = crate::create_text_response(http::StatusCode::OK, "hello");
+        *response.version_mut() = version;
+        response.headers_mut().insert(http::header::TRANSFER_ENCODING, http::HeaderValue::from_str(transfer_encoding).unwrap());
+
+        let mut response_bytes = Vec::new();
+        super::write_http_response(&mut response_bytes, &response).unwrap();
+        String::from_utf8(response_bytes).unwrap()
+    }
+
+    #[test]
+    fn frames_body_by_content_length() {
+        let mut response_bytes = Vec::new();
+        super::write_http_response(&mut response_bytes, &crate::create_text_response(http::StatusCode::OK, "hello")).unwrap();
+        assert_eq!(response_bytes, b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\nhello");
+    }
+
+    #[test]
+    fn chunks_body_only_for_http_11_chunked() {
+        assert_eq!(write_with_transfer_encoding(http::Version::HTTP_11, "chunked"), "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n");
+        assert_eq!(write_with_transfer_encoding(http::Version::HTTP_10, "chunked"), "HTTP/1.0 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\nhello");
+        assert_eq!(write_with_transfer_encoding(http::Version::HTTP_11, "gzip"), "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\nhello");
+    }
+}
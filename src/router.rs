@@ -0,0 +1,462 @@
+/// Values captured from the request path by a route's `:name` and `*name` segments.
+/// The router adds these to each request's extensions before calling the route's handler.
+#[derive(Debug, Clone, Default)]
+pub struct PathParams {
+    params: Vec<(String, String)>,
+    rest: Option<String>,
+}
+
+impl PathParams {
+    /// The (percent-decoded) value captured by the segment with this name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, param_value)| param_value.as_str())
+    }
+
+    /// Every captured value, in the order its segment appears in the route.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(param_name, param_value)| (param_name.as_str(), param_value.as_str()))
+    }
+
+    /// The rest of the path captured by a `*name` wildcard segment, if the route ended with one.
+    pub fn rest(&self) -> Option<&str> {
+        self.rest.as_deref()
+    }
+}
+
+enum PathSegment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+enum RouteTarget {
+    Handler(std::sync::Arc<dyn crate::Handler>),
+    Nested(Router),
+}
+
+enum Resolution<'r> {
+    // Along with the handler, any nested routers it's under (whose state and middleware apply to it), outermost first.
+    Found(std::sync::Arc<dyn crate::Handler>, Vec<&'r Router>),
+    // The path matched some routes, but none of them for the request's method.
+    MethodNotAllowed(Vec<http::Method>),
+    NotFound,
+}
+
+struct Route {
+    // A route without a method matches every method.
+    method: Option<http::Method>,
+    segments: Vec<PathSegment>,
+    target: RouteTarget,
+}
+
+/// Dispatches requests to handlers by method and path.
+///
+/// Route paths are made up of '/'-separated segments, each of which is either:
+/// - Literal text, matching exactly, e.g. "/users",
+/// - A parameter, matching any one non-empty segment, e.g. "/users/:id",
+/// - Or (only at the end) a wildcard, matching the rest of the path, e.g. "/files/*path".
+///
+/// Routes are tried in the order they're added, and the first match handles the request.
+/// When a path matches only routes for other methods, the router answers 405 Method Not Allowed with an `Allow` header,
+/// and it answers `OPTIONS` requests (including `OPTIONS *`, for the whole router) itself unless a route handles them.
+/// `HEAD` requests without a `HEAD` route of their own are handled by the path's `GET` route.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    layers: Vec<std::sync::Arc<dyn crate::Middleware>>,
+    states: http::Extensions,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router {
+            routes: Vec::new(),
+            layers: Vec::new(),
+            states: http::Extensions::new(),
+        }
+    }
+
+    /// Share a value with every request this router handles, including those handled by nested routers,
+    /// for handlers and middleware to get as a `State<T>`. A nested router's state replaces its parent's of the same type.
+    pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.states.insert(crate::State(std::sync::Arc::new(value)));
+        self
+    }
+
+    /// Wrap every request this router handles in a middleware, including those handled by nested routers.
+    /// Layers run in the order they're added, and a nested router's own layers run inside its parent's.
+    pub fn layer(mut self, middleware: impl crate::Middleware) -> Self {
+        self.layers.push(std::sync::Arc::new(middleware));
+        self
+    }
+
+    /// Handle requests with this method and path.
+    ///
+    /// # Panics
+    /// If a wildcard segment isn't the last segment of the path.
+    pub fn route(self, method: http::Method, path: &str, handler: impl crate::Handler) -> Self {
+        self.add_route(Some(method), path, RouteTarget::Handler(std::sync::Arc::new(handler)))
+    }
+
+    /// Handle requests with this path, whatever their method.
+    pub fn any(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.add_route(None, path, RouteTarget::Handler(std::sync::Arc::new(handler)))
+    }
+
+    pub fn get(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.route(http::Method::GET, path, handler)
+    }
+
+    pub fn post(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.route(http::Method::POST, path, handler)
+    }
+
+    pub fn put(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.route(http::Method::PUT, path, handler)
+    }
+
+    pub fn patch(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.route(http::Method::PATCH, path, handler)
+    }
+
+    pub fn delete(self, path: &str, handler: impl crate::Handler) -> Self {
+        self.route(http::Method::DELETE, path, handler)
+    }
+
+    /// Hand requests under this path prefix to another router, which matches its routes against the rest of the path.
+    /// Parameters in the prefix are captured just like in a route, e.g. "/users/:id" nesting a router with "/posts".
+    pub fn nest(self, prefix: &str, router: Router) -> Self {
+        self.add_route(None, prefix, RouteTarget::Nested(router))
+    }
+
+    fn add_route(mut self, method: Option<http::Method>, path: &str, target: RouteTarget) -> Self {
+        let segments = parse_route_path(path);
+        if let RouteTarget::Nested(_) = target
+            && let Some(PathSegment::Wildcard(_)) = segments.last() {
+            panic!("Nested router prefix {} can't end with a wildcard", path);
+        }
+
+        self.routes.push(Route {
+            method,
+            segments,
+            target,
+        });
+        self
+    }
+
+    fn resolve(&self, method: &http::Method, path_segments: &[&str], path_params: &mut PathParams) -> Resolution<'_> {
+        let mut allowed_methods = Vec::new();
+        for route in &self.routes {
+            let route_params_len = path_params.params.len();
+
+            match &route.target {
+                RouteTarget::Handler(handler) => {
+                    if match_segments(&route.segments, path_segments, path_params, true).is_some() {
+                        match &route.method {
+                            Some(route_method) if route_method != method => add_allowed_method(&mut allowed_methods, route_method),
+                            _ => return Resolution::Found(handler.clone(), Vec::new()),
+                        }
+                    }
+                },
+                RouteTarget::Nested(router) => {
+                    if let Some(matched_len) = match_segments(&route.segments, path_segments, path_params, false) {
+                        match router.resolve(method, &path_segments[matched_len..], path_params) {
+                            Resolution::Found(handler, mut nested_routers) => {
+                                nested_routers.insert(0, router);
+                                return Resolution::Found(handler, nested_routers);
+                            },
+                            Resolution::MethodNotAllowed(nested_allowed_methods) => {
+                                for nested_allowed_method in &nested_allowed_methods {
+                                    add_allowed_method(&mut allowed_methods, nested_allowed_method);
+                                }
+                            },
+                            Resolution::NotFound => {}
+                        }
+                    }
+                }
+            }
+
+            // This route didn't match after all, so forget anything it captured.
+            path_params.params.truncate(route_params_len);
+            path_params.rest = None;
+        }
+
+        if allowed_methods.is_empty() {
+            Resolution::NotFound
+        } else {
+            Resolution::MethodNotAllowed(allowed_methods)
+        }
+    }
+
+    fn collect_methods(&self, methods: &mut Vec<http::Method>) {
+        for route in &self.routes {
+            match &route.target {
+                RouteTarget::Handler(_) => {
+                    if let Some(route_method) = &route.method {
+                        add_allowed_method(methods, route_method);
+                    }
+                },
+                RouteTarget::Nested(router) => router.collect_methods(methods),
+            }
+        }
+    }
+}
+
+impl crate::Handler for Router {
+    fn call(&self, mut request: crate::Request) -> crate::Response {
+        request.extensions_mut().extend(self.states.clone());
+
+        let endpoint = |request| self.dispatch(request);
+        crate::middleware::Next::new(&self.layers, &endpoint).run(request)
+    }
+}
+
+impl Router {
+    fn dispatch(&self, mut request: crate::Request) -> crate::Response {
+        // "OPTIONS *" asks about the server as a whole, rather than any one path.
+        if request.method() == http::Method::OPTIONS && request.uri() == "*" {
+            let mut allowed_methods = Vec::new();
+            self.collect_methods(&mut allowed_methods);
+            return create_allow_response(http::StatusCode::NO_CONTENT, "", allowed_methods);
+        }
+
+        let path = request.uri().path().to_string();
+        let path_segments = split_path(&path);
+
+        let mut path_params = PathParams::default();
+        let mut resolution = self.resolve(request.method(), &path_segments, &mut path_params);
+        if request.method() == http::Method::HEAD && !matches!(resolution, Resolution::Found(..)) {
+            // The GET handler's response stands in for HEAD, and the server leaves out its body.
+            resolution = self.resolve(&http::Method::GET, &path_segments, &mut path_params);
+        }
+
+        match resolution {
+            Resolution::Found(handler, nested_routers) => {
+                request.extensions_mut().insert(path_params);
+                for nested_router in &nested_routers {
+                    request.extensions_mut().extend(nested_router.states.clone());
+                }
+
+                let nested_layers: Vec<std::sync::Arc<dyn crate::Middleware>> = nested_routers.iter()
+                    .flat_map(|nested_router| nested_router.layers.iter().cloned())
+                    .collect();
+                let endpoint = |request| handler.call(request);
+                crate::middleware::Next::new(&nested_layers, &endpoint).run(request)
+            },
+            Resolution::MethodNotAllowed(allowed_methods) => {
+                if request.method() == http::Method::OPTIONS {
+                    create_allow_response(http::StatusCode::NO_CONTENT, "", allowed_methods)
+                } else {
+                    let message = format!("Method {} not allowed for path {}", request.method(), path);
+                    create_allow_response(http::StatusCode::METHOD_NOT_ALLOWED, &message, allowed_methods)
+                }
+            },
+            Resolution::NotFound => {
+                crate::create_text_response(http::StatusCode::NOT_FOUND, &format!("Unrecognized path {}", path))
+            }
+        }
+    }
+}
+
+fn add_allowed_method(allowed_methods: &mut Vec<http::Method>, method: &http::Method) {
+    if !allowed_methods.contains(method) {
+        allowed_methods.push(method.clone());
+    }
+}
+
+fn create_allow_response(status: http::StatusCode, message: &str, mut allowed_methods: Vec<http::Method>) -> crate::Response {
+    // GET routes also answer HEAD, and the router answers OPTIONS itself, so those are allowed too.
+    if allowed_methods.contains(&http::Method::GET) {
+        add_allowed_method(&mut allowed_methods, &http::Method::HEAD);
+    }
+    add_allowed_method(&mut allowed_methods, &http::Method::OPTIONS);
+    let allow_value = allowed_methods.iter().map(|allowed_method| allowed_method.as_str()).collect::<Vec<&str>>().join(", ");
+
+    let mut response = crate::create_text_response(status, message);
+    if message.is_empty() {
+        response.headers_mut().remove(http::header::CONTENT_TYPE);
+    }
+    response.headers_mut().insert(http::header::ALLOW, http::HeaderValue::from_str(&allow_value).unwrap());
+    response
+}
+
+fn parse_route_path(path: &str) -> Vec<PathSegment> {
+    let path_segments = split_path(path);
+
+    let mut segments = Vec::with_capacity(path_segments.len());
+    for (segment_index, path_segment) in path_segments.iter().enumerate() {
+        if let Some(param_name) = path_segment.strip_prefix(':') {
+            segments.push(PathSegment::Param(param_name.to_string()));
+        } else if let Some(wildcard_name) = path_segment.strip_prefix('*') {
+            if segment_index + 1 != path_segments.len() {
+                panic!("Route path {} has a wildcard before its last segment", path);
+            }
+            segments.push(PathSegment::Wildcard(wildcard_name.to_string()));
+        } else {
+            segments.push(PathSegment::Literal(path_segment.to_string()));
+        }
+    }
+
+    segments
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    // "/" has no segments at all, while "/users/" has two: "users" and "".
+    let path = path.strip_prefix('/').unwrap_or(path);
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    path.split('/').collect()
+}
+
+fn match_segments(segments: &[PathSegment], path_segments: &[&str], path_params: &mut PathParams, match_whole_path: bool) -> Option<usize> {
+    // Returns how many path segments were matched, which (unless matching only a prefix) is all of them.
+    let mut path_pos = 0;
+    for segment in segments {
+        match segment {
+            PathSegment::Literal(literal) => {
+                if path_segments.get(path_pos) != Some(&literal.as_str()) {
+                    return None;
+                }
+            },
+            PathSegment::Param(param_name) => {
+                // A parameter needs something to capture, so "/users/" doesn't match "/users/:id".
+                let param_value = path_segments.get(path_pos).filter(|param_value| !param_value.is_empty())?;
+                path_params.params.push((param_name.clone(), percent_decode(param_value)));
+            },
+            PathSegment::Wildcard(wildcard_name) => {
+                // A wildcard matches whatever's left of the path, even if that's nothing.
+                let rest_segments: Vec<String> = path_segments[path_pos..].iter().map(|path_segment| percent_decode(path_segment)).collect();
+                let rest = rest_segments.join("/");
+                path_params.params.push((wildcard_name.clone(), rest.clone()));
+                path_params.rest = Some(rest);
+                return Some(path_segments.len());
+            }
+        }
+        path_pos += 1;
+    }
+
+    if match_whole_path && path_pos != path_segments.len() {
+        return None;
+    }
+
+    Some(path_pos)
+}
+
+pub(crate) fn percent_decode(encoded: &str) -> String {
+    // Invalid escapes are left as they are, and invalid UTF-8 is replaced, rather than failing the whole request.
+    let encoded_bytes = encoded.as_bytes();
+    let mut decoded_bytes = Vec::with_capacity(encoded_bytes.len());
+
+    let mut encoded_pos = 0;
+    while encoded_pos < encoded_bytes.len() {
+        let escaped_byte = match encoded_bytes.get(encoded_pos..encoded_pos+3) {
+            Some([b'%', high_digit, low_digit]) => {
+                let high_value = (*high_digit as char).to_digit(16);
+                let low_value = (*low_digit as char).to_digit(16);
+                high_value.zip(low_value).map(|(high_value, low_value)| (high_value * 16 + low_value) as u8)
+            },
+            _ => None
+        };
+
+        match escaped_byte {
+            Some(decoded_byte) => {
+                decoded_bytes.push(decoded_byte);
+                encoded_pos += 3;
+            },
+            None => {
+                decoded_bytes.push(encoded_bytes[encoded_pos]);
+                encoded_pos += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded_bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    fn show_params(request: crate::Request) -> crate::Response {
+        let path_params = request.extensions().get::<super::PathParams>().cloned().unwrap_or_default();
+        let mut params_text: Vec<String> = path_params.iter().map(|(param_name, param_value)| format!("{}={}", param_name, param_value)).collect();
+        if let Some(rest) = path_params.rest() {
+            params_text.push(format!("rest={}", rest));
+        }
+        crate::create_text_response(http::StatusCode::OK, &params_text.join(" "))
+    }
+
+    #[test]
+    fn captures_params_and_wildcards() {
+        let router = super::Router::new()
+            .get("/users/:id", show_params)
+            .get("/users/:id/posts/:post_id", show_params)
+            .get("/files/*path", show_params);
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/a%20b")), "id=a b");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/7/posts/12")), "id=7 post_id=12");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/files/css/site.css")), "path=css/site.css rest=css/site.css");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/files")), "path= rest=");
+        assert_eq!(crate::test_support::call(&router, http::Method::GET, "/users").status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(crate::test_support::call(&router, http::Method::GET, "/users/").status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(crate::test_support::call(&router, http::Method::GET, "/users//posts/12").status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(crate::test_support::call(&router, http::Method::GET, "/users/7/extra").status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn tries_routes_in_order() {
+        let router = super::Router::new()
+            .get("/users/me", |_request| crate::create_text_response(http::StatusCode::OK, "me"))
+            .get("/users/:id", show_params);
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/me")), "me");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/you")), "id=you");
+    }
+
+    #[test]
+    fn nests_routers_under_prefixes() {
+        let posts_router = super::Router::new().get("/posts/:post_id", show_params);
+        let router = super::Router::new()
+            .nest("/users/:id", posts_router)
+            .get("/users/:name/about", show_params);
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/7/posts/12")), "id=7 post_id=12");
+        // Params captured by a nested prefix that didn't lead anywhere are forgotten.
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::GET, "/users/7/about")), "name=7");
+    }
+
+    #[test]
+    fn answers_405_with_allow() {
+        let router = super::Router::new()
+            .get("/items", show_params)
+            .post("/items", show_params)
+            .nest("/items", super::Router::new().delete("/", show_params));
+
+        let response = crate::test_support::call(&router, http::Method::PUT, "/items");
+        assert_eq!(response.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[http::header::ALLOW], "GET, POST, DELETE, HEAD, OPTIONS");
+
+        let response = crate::test_support::call(&router, http::Method::OPTIONS, "/items");
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[http::header::ALLOW], "GET, POST, DELETE, HEAD, OPTIONS");
+        assert!(response.body().is_empty());
+
+        let response = crate::test_support::call(&router, http::Method::OPTIONS, "*");
+        assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[http::header::ALLOW], "GET, POST, DELETE, HEAD, OPTIONS");
+    }
+
+    #[test]
+    fn falls_back_to_get_for_head() {
+        let router = super::Router::new()
+            .get("/page", |_request| crate::create_text_response(http::StatusCode::OK, "get"))
+            .route(http::Method::HEAD, "/explicit", |_request| crate::create_text_response(http::StatusCode::OK, "head"))
+            .get("/explicit", |_request| crate::create_text_response(http::StatusCode::OK, "get"));
+
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::HEAD, "/page")), "get");
+        assert_eq!(crate::test_support::body_text(&crate::test_support::call(&router, http::Method::HEAD, "/explicit")), "head");
+        assert_eq!(crate::test_support::call(&router, http::Method::HEAD, "/missing").status(), http::StatusCode::NOT_FOUND);
+    }
+}
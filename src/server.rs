@@ -0,0 +1,973 @@
+/// Instructions from a handler to the server, attached to a response through its extensions, e.g.:
+/// `response.extensions_mut().insert(ServerControl { should_stop: true })`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServerControl {
+    /// Stop the server (gracefully) once this response is written.
+    pub should_stop: bool,
+}
+
+struct ConnectionResult {
+    should_stop: bool,
+    keep_alive: bool,
+}
+
+#[derive(Clone)]
+struct ConnectionSettings {
+    parser_limits: crate::ParserLimits,
+    // Reads block until the client sends something, so a stalled client is cut off after this long.
+    read_timeout: std::time::Duration,
+    // How long a persistent connection may sit between requests before it's closed.
+    idle_timeout: std::time::Duration,
+    // How many requests a persistent connection may carry before it's closed.
+    max_requests: usize,
+}
+
+#[derive(Clone)]
+struct PoolSettings {
+    // How many connections can be handled at once.
+    worker_count: usize,
+    // How many accepted connections can wait for a worker before new ones are turned away.
+    queue_depth: usize,
+    // How long in-flight requests get to finish when the server is stopping, before their connections are cut off.
+    shutdown_grace_period: std::time::Duration,
+}
+
+struct TrackedConnection {
+    stream: Option<std::net::TcpStream>,
+    idle: bool,
+}
+
+// Keeps a handle on every open connection, so that stopping the server can reach connections
+// whose workers are blocked waiting on a read.
+struct ConnectionTracker {
+    stopping: std::sync::atomic::AtomicBool,
+    next_connection_id: std::sync::atomic::AtomicU64,
+    connections: std::sync::Mutex<std::collections::HashMap<u64, TrackedConnection>>,
+}
+
+impl ConnectionTracker {
+    fn new() -> ConnectionTracker {
+        ConnectionTracker {
+            stopping: std::sync::atomic::AtomicBool::new(false),
+            next_connection_id: std::sync::atomic::AtomicU64::new(0),
+            connections: std::sync::Mutex::new(std::collections::HashMap::new()),
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn register(&self, stream: &std::net::TcpStream) -> u64 {
+        let connection_id = self.next_connection_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let tracked_connection = TrackedConnection {
+            stream: stream.try_clone().ok(),
+            idle: false,
+        };
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.insert(connection_id, tracked_connection);
+        }
+
+        connection_id
+    }
+
+    fn unregister(&self, connection_id: u64) {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.remove(&connection_id);
+        }
+    }
+
+    /// Mark a connection as idle between requests, unless the server is stopping, in which case it should close instead.
+    fn enter_idle(&self, connection_id: u64) -> bool {
+        // Checking for stopping under the lock means that close_idle_connections() (which runs after stopping is set)
+        // either already ran, and this sees stopping, or runs afterwards, and sees this connection as idle.
+        let Ok(mut connections) = self.connections.lock() else {
+            return !self.is_stopping();
+        };
+        if self.is_stopping() {
+            return false;
+        }
+        if let Some(tracked_connection) = connections.get_mut(&connection_id) {
+            tracked_connection.idle = true;
+        }
+        true
+    }
+
+    fn leave_idle(&self, connection_id: u64) {
+        if let Ok(mut connections) = self.connections.lock()
+            && let Some(tracked_connection) = connections.get_mut(&connection_id) {
+            tracked_connection.idle = false;
+        }
+    }
+
+    fn close_idle_connections(&self) {
+        // Shutting down the read side wakes a worker blocked on the connection, as if the client had closed it.
+        // Connections become idle (and check for stopping) under the same lock, so none can slip past this; see enter_idle().
+        if let Ok(connections) = self.connections.lock() {
+            for tracked_connection in connections.values().filter(|tracked_connection| tracked_connection.idle) {
+                if let Some(stream) = &tracked_connection.stream {
+                    let _ = stream.shutdown(std::net::Shutdown::Read);
+                }
+            }
+        }
+    }
+
+    fn close_all_connections(&self) {
+        if let Ok(connections) = self.connections.lock() {
+            for tracked_connection in connections.values() {
+                if let Some(stream) = &tracked_connection.stream {
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                }
+            }
+        }
+    }
+
+    fn open_connection_count(&self) -> usize {
+        self.connections.lock().map(|connections| connections.len()).unwrap_or(0)
+    }
+}
+
+// Stopping the server means no longer accepting connections, so the accept loop is woken up
+// (it spends its time blocked in accept()) with a connection of its own.
+fn request_stop(connection_tracker: &ConnectionTracker, wake_addr: Option<std::net::SocketAddr>) {
+    connection_tracker.stopping.store(true, std::sync::atomic::Ordering::SeqCst);
+    if let Some(wake_addr) = wake_addr {
+        let _ = std::net::TcpStream::connect(wake_addr);
+    }
+}
+
+struct WorkerPool {
+    stream_txchan: std::sync::mpsc::SyncSender<std::net::TcpStream>,
+    stream_rxchan: std::sync::Arc<std::sync::Mutex<std::sync::mpsc::Receiver<std::net::TcpStream>>>,
+    workers: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    fn new(pool_settings: &PoolSettings, connection_settings: std::sync::Arc<ConnectionSettings>, handler: std::sync::Arc<dyn crate::Handler>, connection_tracker: std::sync::Arc<ConnectionTracker>, control_txchan: std::sync::mpsc::Sender<ConnectionResult>) -> WorkerPool {
+        // Every worker pulls its next connection from the same bounded queue.
+        let (stream_txchan, stream_rxchan) = std::sync::mpsc::sync_channel::<std::net::TcpStream>(pool_settings.queue_depth);
+        let stream_rxchan = std::sync::Arc::new(std::sync::Mutex::new(stream_rxchan));
+
+        let mut workers = Vec::with_capacity(pool_settings.worker_count);
+        for _ in 0..pool_settings.worker_count {
+            let worker_stream_rxchan = stream_rxchan.clone();
+            let worker_connection_settings = connection_settings.clone();
+            let worker_handler = handler.clone();
+            let worker_connection_tracker = connection_tracker.clone();
+            let worker_control_txchan = control_txchan.clone();
+            workers.push(std::thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting for a connection, not while handling it.
+                    let next_stream = match worker_stream_rxchan.lock() {
+                        Ok(stream_rxchan) => stream_rxchan.recv(),
+                        Err(_) => break
+                    };
+                    let Ok(stream) = next_stream else {
+                        // The pool is shutting down.
+                        break;
+                    };
+
+                    let connection_id = worker_connection_tracker.register(&stream);
+                    let handler_result = handle_connection(&stream, &worker_connection_settings, worker_handler.as_ref(), &worker_connection_tracker, connection_id);
+                    worker_connection_tracker.unregister(connection_id);
+
+                    // The server may have stopped listening for results already, which is fine.
+                    let _ = worker_control_txchan.send(handler_result);
+                }
+            }));
+        }
+
+        WorkerPool {
+            stream_txchan,
+            stream_rxchan,
+            workers,
+        }
+    }
+
+    fn dispatch(&self, stream: std::net::TcpStream) -> Result<(), std::net::TcpStream> {
+        match self.stream_txchan.try_send(stream) {
+            Ok(()) => Ok(()),
+            Err(std::sync::mpsc::TrySendError::Full(stream)) => Err(stream),
+            Err(std::sync::mpsc::TrySendError::Disconnected(stream)) => Err(stream)
+        }
+    }
+
+    fn join(self, grace_period: std::time::Duration, connection_tracker: &ConnectionTracker) {
+        // Closing the queue lets each worker exit once it's done with its current connection.
+        // Connections still queued haven't sent anything the server has read yet, so they're just closed.
+        drop(self.stream_txchan);
+        if let Ok(stream_rxchan) = self.stream_rxchan.lock() {
+            let queued_count = stream_rxchan.try_iter().count();
+            if queued_count > 0 {
+                log::debug!("Closed {} queued connections", queued_count);
+            }
+        }
+
+        // Workers get the grace period to finish up, after which their connections are cut off.
+        let grace_deadline = std::time::Instant::now() + grace_period;
+        while !self.workers.iter().all(|worker| worker.is_finished()) {
+            if std::time::Instant::now() >= grace_deadline {
+                log::warn!("Closing {} connections still open after the shutdown grace period", connection_tracker.open_connection_count());
+                connection_tracker.close_all_connections();
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        for worker in self.workers {
+            if worker.join().is_err() {
+                log::error!("Worker thread panicked");
+            }
+        }
+    }
+}
+
+fn reject_busy_stream(stream: std::net::TcpStream) {
+    // Every worker is busy and the queue is full, so the client is asked to come back later.
+    log::warn!("Rejecting connection, all workers are busy");
+
+    let mut response = crate::create_text_response(http::StatusCode::SERVICE_UNAVAILABLE, "Server is busy");
+    response.headers_mut().insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+    response.headers_mut().insert(http::header::RETRY_AFTER, http::HeaderValue::from_static("1"));
+
+    // This happens on the accept thread, so a client that won't take the response can't hold it up for long.
+    let _ = stream.set_write_timeout(Some(std::time::Duration::from_secs(1)));
+    let mut response_bytes = Vec::new();
+    let write_result = crate::write_http_response(&mut response_bytes, &response)
+        .and_then(|()| std::io::Write::write_all(&mut &stream, &response_bytes));
+    if let Err(write_error) = write_result {
+        log::error!("Response write error: {}", write_error);
+        return;
+    }
+
+    // Closing a socket with unread data resets the connection, which can discard the response before the client reads it.
+    // So the response is followed by a clean end, and whatever the client has already sent is read and thrown away,
+    // without waiting for any more.
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+    if stream.set_nonblocking(true).is_ok() {
+        let mut discard_bytes = [0u8; 4096];
+        while let Ok(read_len) = std::io::Read::read(&mut &stream, &mut discard_bytes) {
+            if read_len == 0 {
+                break;
+            }
+        }
+    }
+}
+
+fn connection_has_option(headers: &http::HeaderMap, option: &str) -> bool {
+    // The Connection header is a comma-separated list of case-insensitive options, e.g. "keep-alive, Upgrade".
+    headers.get_all(http::header::CONNECTION).iter()
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|value_str| value_str.split(','))
+        .any(|connection_option| connection_option.trim().eq_ignore_ascii_case(option))
+}
+
+fn request_wants_keep_alive<B>(request: &http::Request<B>) -> bool {
+    // HTTP/1.1 connections persist unless either side says otherwise, while HTTP/1.0 connections have to ask to persist.
+    match request.version() {
+        http::Version::HTTP_11 => !connection_has_option(request.headers(), "close"),
+        _ => connection_has_option(request.headers(), "keep-alive")
+    }
+}
+
+fn handle_request_stream(request_reader: &mut impl std::io::BufRead, response_writer: &mut impl std::io::Write, settings: &ConnectionSettings, handler: &dyn crate::Handler, connection_tracker: &ConnectionTracker, keep_alive_allowed: bool) -> ConnectionResult {
+    let mut control_result = ConnectionResult {
+        should_stop: false,
+        keep_alive: false,
+    };
+
+    let mut request_http_version: http::Version = http::Version::HTTP_10;
+    let mut request_is_head = false;
+    let mut response: http::Response<Vec<u8>>;
+    match crate::read_http_request(request_reader, &settings.parser_limits) {
+        Ok(request) => {
+            log::info!("Read request: {} {}", request.method(), request.uri());
+
+            request_http_version = request.version();
+            request_is_head = request.method() == http::Method::HEAD;
+            control_result.keep_alive = keep_alive_allowed && request_wants_keep_alive(&request);
+
+            // A handler that panics only fails its own request; the worker carries on with the next connection.
+            let handler_result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler.call(request)));
+            response = match handler_result {
+                Ok(response) => response,
+                Err(_) => {
+                    log::error!("Handler panicked");
+                    let mut panic_response = crate::create_text_response(http::StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+                    panic_response.headers_mut().insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+                    panic_response
+                }
+            };
+
+            if let Some(server_control) = response.extensions().get::<ServerControl>() {
+                control_result.should_stop = server_control.should_stop;
+            }
+            if control_result.should_stop {
+                control_result.keep_alive = false;
+            }
+        },
+        Err(crate::RequestParseError::Io(io_error)) => {
+            // The transport itself failed, so there's no point trying to write a response to it.
+            log::error!("Request read error: {}", io_error);
+            return control_result;
+        },
+        Err(read_error) => {
+            // After a malformed request, there's no telling where the next request would start,
+            // so the connection is closed rather than kept alive.
+            log::error!("Request read error: {}", read_error);
+
+            let response_body = &read_error.to_string();
+
+            response = crate::create_text_response(read_error.status(), response_body);
+        }
+    }
+
+    *response.version_mut() = request_http_version;
+
+    // The response says whether the connection persists, wherever that differs from the protocol's default.
+    // A server that started stopping while the request was in flight closes the connection after it.
+    if connection_has_option(response.headers(), "close") || connection_tracker.is_stopping() {
+        control_result.keep_alive = false;
+    }
+    if !control_result.keep_alive && request_http_version == http::Version::HTTP_11 {
+        response.headers_mut().insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+    }
+    if control_result.keep_alive && request_http_version == http::Version::HTTP_10 {
+        response.headers_mut().insert(http::header::CONNECTION, http::HeaderValue::from_static("keep-alive"));
+    }
+
+    // A HEAD response carries the headers the body would have had, but never the body itself.
+    let write_result = if request_is_head {
+        crate::write_http_head_response(response_writer, &response)
+    } else {
+        crate::write_http_response(response_writer, &response)
+    };
+    if let Err(write_error) = write_result {
+        log::error!("Response write error: {}", write_error);
+        control_result.keep_alive = false;
+        return control_result;
+    }
+
+    control_result
+}
+
+fn handle_connection(stream: &std::net::TcpStream, settings: &ConnectionSettings, handler: &dyn crate::Handler, connection_tracker: &ConnectionTracker, connection_id: u64) -> ConnectionResult {
+    let mut request_reader = std::io::BufReader::new(stream);
+    let mut response_writer = std::io::BufWriter::new(stream);
+
+    // A connection that was waiting in the queue when the server started stopping is closed without a request.
+    if connection_tracker.is_stopping() {
+        return ConnectionResult {
+            should_stop: false,
+            keep_alive: false,
+        };
+    }
+
+    let mut requests_handled: usize = 0;
+    loop {
+        // Between requests (when nothing's pipelined), a persistent connection is idle,
+        // and is closed right away if the server is stopping.
+        let connection_idle = requests_handled > 0 && request_reader.buffer().is_empty();
+        if connection_idle && !connection_tracker.enter_idle(connection_id) {
+            break;
+        }
+
+        // Wait (up to the idle timeout) for the start of the next request.
+        if let Err(timeout_error) = stream.set_read_timeout(Some(settings.idle_timeout)) {
+            log::error!("TCP stream error: {}", timeout_error);
+            break;
+        }
+        let read_result = std::io::BufRead::fill_buf(&mut request_reader);
+        if connection_idle {
+            connection_tracker.leave_idle(connection_id);
+        }
+        match read_result {
+            Ok(read_bytes) => {
+                if read_bytes.is_empty() {
+                    // The client closed the connection between requests, which is perfectly normal.
+                    break;
+                }
+            },
+            Err(read_error) => {
+                match read_error.kind() {
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                        log::debug!("Closing idle connection");
+                    },
+                    std::io::ErrorKind::Interrupted => {
+                        continue;
+                    },
+                    _ => {
+                        log::error!("TCP stream error: {}", read_error);
+                    }
+                }
+                break;
+            }
+        }
+
+        // Once a request has started, the rest of it gets the (longer) read timeout.
+        if let Err(timeout_error) = stream.set_read_timeout(Some(settings.read_timeout)) {
+            log::error!("TCP stream error: {}", timeout_error);
+            break;
+        }
+
+        requests_handled += 1;
+        let keep_alive_allowed = requests_handled < settings.max_requests;
+        let control_result = handle_request_stream(&mut request_reader, &mut response_writer, settings, handler, connection_tracker, keep_alive_allowed);
+        let connection_done = control_result.should_stop || !control_result.keep_alive;
+
+        // When a client pipelines requests, the next one may already be buffered after this one.
+        // Its response is written in order right after this one, and they're flushed out together
+        // once there's nothing more buffered to answer (or the connection is closing).
+        if connection_done || request_reader.buffer().is_empty() {
+            let flush_result = std::io::Write::flush(&mut response_writer);
+            if let Err(flush_error) = flush_result {
+                log::error!("Response write error: {}", flush_error);
+                break;
+            }
+        }
+
+        if connection_done {
+            return control_result;
+        }
+    }
+
+    ConnectionResult {
+        should_stop: false,
+        keep_alive: false,
+    }
+}
+
+fn local_wake_addr(tcp_listener: &std::net::TcpListener) -> Option<std::net::SocketAddr> {
+    // A listener bound to every interface (e.g. 0.0.0.0) can be reached through loopback.
+    let mut wake_addr = tcp_listener.local_addr().ok()?;
+    if wake_addr.ip().is_unspecified() {
+        match wake_addr {
+            std::net::SocketAddr::V4(_) => wake_addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into()),
+            std::net::SocketAddr::V6(_) => wake_addr.set_ip(std::net::Ipv6Addr::LOCALHOST.into())
+        }
+    }
+
+    Some(wake_addr)
+}
+
+/// Configures a `Server`, starting from `Server::builder()`.
+pub struct ServerBuilder {
+    bind_addrs: Vec<std::net::SocketAddr>,
+    bind_error: Option<std::io::Error>,
+    handler: Option<std::sync::Arc<dyn crate::Handler>>,
+    layers: Vec<std::sync::Arc<dyn crate::Middleware>>,
+    states: http::Extensions,
+    connection_settings: ConnectionSettings,
+    pool_settings: PoolSettings,
+}
+
+impl ServerBuilder {
+    fn new() -> ServerBuilder {
+        ServerBuilder {
+            bind_addrs: Vec::new(),
+            bind_error: None,
+            handler: None,
+            layers: Vec::new(),
+            states: http::Extensions::new(),
+            connection_settings: ConnectionSettings {
+                parser_limits: crate::ParserLimits::default(),
+                read_timeout: std::time::Duration::from_secs(30),
+                idle_timeout: std::time::Duration::from_secs(5),
+                max_requests: 100,
+            },
+            pool_settings: PoolSettings {
+                worker_count: 8,
+                queue_depth: 64,
+                shutdown_grace_period: std::time::Duration::from_secs(10),
+            },
+        }
+    }
+
+    /// The address to listen on, e.g. "0.0.0.0:8080".
+    /// If it resolves to several addresses, the first one that can be bound is used, and if it doesn't resolve, `build()` fails.
+    pub fn bind(mut self, addr: impl std::net::ToSocketAddrs + std::fmt::Debug) -> Self {
+        match addr.to_socket_addrs() {
+            Ok(socket_addrs) => {
+                self.bind_addrs = socket_addrs.collect();
+            },
+            Err(resolve_error) => {
+                self.bind_error = Some(std::io::Error::new(resolve_error.kind(), format!("Couldn't bind to {:?}: {}", addr, resolve_error)));
+            }
+        }
+        self
+    }
+
+    /// The handler that turns each request into a response, e.g. a `Router`.
+    pub fn handler(mut self, handler: impl crate::Handler) -> Self {
+        self.handler = Some(std::sync::Arc::new(handler));
+        self
+    }
+
+    /// Wrap every request the server handles in a middleware, outside of any the handler itself applies.
+    /// Layers run in the order they're added, so the first one added sees each request first.
+    pub fn layer(mut self, middleware: impl crate::Middleware) -> Self {
+        self.layers.push(std::sync::Arc::new(middleware));
+        self
+    }
+
+    /// Share a value with every request, for handlers and middleware to get as a `State<T>`.
+    /// A router's own state of the same type replaces it, for the requests that router handles.
+    pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.states.insert(crate::State(std::sync::Arc::new(value)));
+        self
+    }
+
+    /// How many connections can be handled at once (at least one).
+    pub fn workers(mut self, worker_count: usize) -> Self {
+        self.pool_settings.worker_count = std::cmp::max(worker_count, 1);
+        self
+    }
+
+    /// How many accepted connections can wait for a worker before new ones are answered with 503.
+    pub fn queue_depth(mut self, queue_depth: usize) -> Self {
+        self.pool_settings.queue_depth = queue_depth;
+        self
+    }
+
+    pub fn parser_limits(mut self, parser_limits: crate::ParserLimits) -> Self {
+        self.connection_settings.parser_limits = parser_limits;
+        self
+    }
+
+    /// How long a read may block once a request has started arriving.
+    /// Sockets can't have a zero timeout, so `build()` fails for `Duration::ZERO`.
+    pub fn read_timeout(mut self, read_timeout: std::time::Duration) -> Self {
+        self.connection_settings.read_timeout = read_timeout;
+        self
+    }
+
+    /// How long a persistent connection may sit between requests before it's closed.
+    /// Like the read timeout, this can't be zero.
+    pub fn idle_timeout(mut self, idle_timeout: std::time::Duration) -> Self {
+        self.connection_settings.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// How many requests a persistent connection may carry before it's closed (at least one).
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.connection_settings.max_requests = std::cmp::max(max_requests, 1);
+        self
+    }
+
+    /// How long in-flight requests get to finish when the server is stopping.
+    pub fn shutdown_grace_period(mut self, grace_period: std::time::Duration) -> Self {
+        self.pool_settings.shutdown_grace_period = grace_period;
+        self
+    }
+
+    /// Bind the listener, so the server is ready to `run()`.
+    pub fn build(self) -> Result<Server, std::io::Error> {
+        if let Some(bind_error) = self.bind_error {
+            return Err(bind_error);
+        }
+        let Some(mut handler) = self.handler else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Server has no handler"));
+        };
+        if self.connection_settings.read_timeout.is_zero() || self.connection_settings.idle_timeout.is_zero() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Server read and idle timeouts can't be zero"));
+        }
+        if !self.layers.is_empty() || !self.states.is_empty() {
+            handler = std::sync::Arc::new(crate::middleware::Layered {
+                states: self.states,
+                middleware: self.layers,
+                handler,
+            });
+        }
+        if self.bind_addrs.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Server has no address to bind"));
+        }
+
+        let tcp_listener = std::net::TcpListener::bind(self.bind_addrs.as_slice()).map_err(|bind_error| {
+            let bind_addrs: Vec<String> = self.bind_addrs.iter().map(|bind_addr| bind_addr.to_string()).collect();
+            std::io::Error::new(bind_error.kind(), format!("Couldn't bind to {}: {}", bind_addrs.join(" or "), bind_error))
+        })?;
+        let wake_addr = local_wake_addr(&tcp_listener);
+
+        Ok(Server {
+            tcp_listener,
+            handler,
+            connection_settings: self.connection_settings,
+            pool_settings: self.pool_settings,
+            handle: ServerHandle {
+                connection_tracker: std::sync::Arc::new(ConnectionTracker::new()),
+                wake_addr,
+            },
+        })
+    }
+}
+
+/// An HTTP/1.x server, handling connections on a pool of worker threads.
+pub struct Server {
+    tcp_listener: std::net::TcpListener,
+    handler: std::sync::Arc<dyn crate::Handler>,
+    connection_settings: ConnectionSettings,
+    pool_settings: PoolSettings,
+    handle: ServerHandle,
+}
+
+/// Stops a running `Server`, from any thread.
+#[derive(Clone)]
+pub struct ServerHandle {
+    connection_tracker: std::sync::Arc<ConnectionTracker>,
+    wake_addr: Option<std::net::SocketAddr>,
+}
+
+impl ServerHandle {
+    /// Stop accepting connections, and let in-flight requests finish (within the grace period) before `run()` returns.
+    pub fn stop(&self) {
+        request_stop(&self.connection_tracker, self.wake_addr);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.connection_tracker.is_stopping()
+    }
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// The address the server is listening on, e.g. to find the port chosen when binding port 0.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, std::io::Error> {
+        self.tcp_listener.local_addr()
+    }
+
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
+    /// Accept and handle connections until the server is stopped, either through its `ServerHandle`
+    /// or by a handler's `ServerControl`.
+    pub fn run(self) -> Result<(), std::io::Error> {
+        log::info!("Starting TCP listener");
+
+        let connection_tracker = self.handle.connection_tracker.clone();
+        let wake_addr = self.handle.wake_addr;
+
+        // Workers report each connection's result back over one shared channel.
+        let (control_txchan, control_rxchan) = std::sync::mpsc::channel::<ConnectionResult>();
+        let worker_pool = WorkerPool::new(&self.pool_settings, std::sync::Arc::new(self.connection_settings), self.handler, connection_tracker.clone(), control_txchan);
+
+        // Results are watched on their own thread, since the accept loop spends its time blocked in accept().
+        let control_connection_tracker = connection_tracker.clone();
+        let control_thread = std::thread::spawn(move || {
+            for control_result in control_rxchan {
+                if control_result.should_stop {
+                    log::info!("Stop requested");
+                    request_stop(&control_connection_tracker, wake_addr);
+                    break;
+                }
+            }
+        });
+
+        for listen_result in self.tcp_listener.incoming() {
+            if connection_tracker.is_stopping() {
+                break;
+            }
+
+            match listen_result {
+                Ok(stream) => {
+                    if let Err(stream) = worker_pool.dispatch(stream) {
+                        reject_busy_stream(stream);
+                    }
+                }
+                Err(error) => {
+                    log::error!("TCP listener error: {}", error);
+                }
+            }
+        }
+
+        log::info!("Shutting down");
+
+        // Stop accepting, let in-flight requests finish (closing idle connections right away), and then return.
+        drop(self.tcp_listener);
+        connection_tracker.close_idle_connections();
+        worker_pool.join(self.pool_settings.shutdown_grace_period, &connection_tracker);
+        if control_thread.join().is_err() {
+            log::error!("Control thread panicked");
+        }
+
+        log::info!("Shut down");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn test_settings(max_requests: usize) -> super::ConnectionSettings {
+        super::ConnectionSettings {
+            parser_limits: crate::ParserLimits::default(),
+            read_timeout: std::time::Duration::from_secs(5),
+            idle_timeout: std::time::Duration::from_secs(5),
+            max_requests,
+        }
+    }
+
+    // Answers requests from a fixture the way handle_connection() does from a socket, returning everything written back
+    // and whether the connection would have stayed open after the last request.
+    fn serve_fixture(request_bytes: &[u8], max_requests: usize, handler: &dyn crate::Handler) -> (String, bool) {
+        let settings = test_settings(max_requests);
+        let connection_tracker = super::ConnectionTracker::new();
+        let mut request_reader: &[u8] = request_bytes;
+        let mut response_bytes = Vec::new();
+
+        let mut requests_handled = 0;
+        let mut keep_alive = false;
+        while !request_reader.is_empty() {
+            requests_handled += 1;
+            let control_result = super::handle_request_stream(&mut request_reader, &mut response_bytes, &settings, handler, &connection_tracker, requests_handled < max_requests);
+            keep_alive = control_result.keep_alive;
+            if !keep_alive {
+                break;
+            }
+        }
+
+        (String::from_utf8(response_bytes).unwrap(), keep_alive)
+    }
+
+    fn send_over_tcp(addr: std::net::SocketAddr, request_bytes: &[u8]) -> String {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        std::io::Write::write_all(&mut stream, request_bytes).unwrap();
+
+        let mut response_bytes = Vec::new();
+        let _ = std::io::Read::read_to_end(&mut stream, &mut response_bytes);
+        String::from_utf8(response_bytes).unwrap()
+    }
+
+    fn panic_or_hello(request: crate::Request) -> crate::Response {
+        if request.uri().path() == "/panic" {
+            panic!("handler panic for a test");
+        }
+        crate::create_text_response(http::StatusCode::OK, "hello")
+    }
+
+    #[test]
+    fn answers_handler_panic_with_500_and_close() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /panic HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n", 100, &panic_or_hello);
+        assert!(response_text.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(response_text.contains("connection: close\r\n"));
+        assert!(!response_text.contains("hello"));
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn reports_unresolvable_bind_address() {
+        let Err(build_error) = super::Server::builder().bind("127.0.0.1:http").handler(panic_or_hello).build() else {
+            panic!("server built with an unresolvable address");
+        };
+        assert_eq!(build_error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(build_error.to_string().starts_with("Couldn't bind to \"127.0.0.1:http\": "));
+    }
+
+    #[test]
+    fn rejects_zero_timeouts() {
+        let zero_read_timeout = super::Server::builder().bind("127.0.0.1:0").handler(panic_or_hello).read_timeout(std::time::Duration::ZERO).build();
+        let zero_idle_timeout = super::Server::builder().bind("127.0.0.1:0").handler(panic_or_hello).idle_timeout(std::time::Duration::ZERO).build();
+        for build_result in [zero_read_timeout, zero_idle_timeout] {
+            let Err(build_error) = build_result else {
+                panic!("server built with a zero timeout");
+            };
+            assert_eq!(build_error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn workers_survive_handler_panics() {
+        let server = super::Server::builder()
+            .bind("127.0.0.1:0")
+            .handler(panic_or_hello)
+            .workers(1)
+            .build()
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let server_handle = server.handle();
+        let server_thread = std::thread::spawn(move || server.run());
+
+        for _ in 0..3 {
+            assert!(send_over_tcp(server_addr, b"GET /panic HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 500 "));
+        }
+        assert!(send_over_tcp(server_addr, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").ends_with("hello"));
+
+        server_handle.stop();
+        server_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn shutdown_is_bounded_by_grace_period() {
+        let server = super::Server::builder()
+            .bind("127.0.0.1:0")
+            .handler(panic_or_hello)
+            .workers(1)
+            .queue_depth(4)
+            .idle_timeout(std::time::Duration::from_secs(5))
+            .shutdown_grace_period(std::time::Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let server_handle = server.handle();
+        let server_thread = std::thread::spawn(move || server.run());
+
+        // One connection keeps the only worker busy with a half-sent request, and two more wait in the queue.
+        let mut busy_stream = std::net::TcpStream::connect(server_addr).unwrap();
+        std::io::Write::write_all(&mut busy_stream, b"GET / HTTP/1.1\r\n").unwrap();
+        let queued_streams: Vec<std::net::TcpStream> = (0..2).map(|_| std::net::TcpStream::connect(server_addr).unwrap()).collect();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let stop_start = std::time::Instant::now();
+        server_handle.stop();
+        server_thread.join().unwrap().unwrap();
+        assert!(stop_start.elapsed() < std::time::Duration::from_secs(3), "stopping took {:?}", stop_start.elapsed());
+        drop(queued_streams);
+    }
+
+    fn echo_path(request: crate::Request) -> crate::Response {
+        crate::create_text_response(http::StatusCode::OK, request.uri().path())
+    }
+
+    #[test]
+    fn keeps_http_11_connections_alive_by_default() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n", 100, &echo_path);
+        assert_eq!(response_text, "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 2\r\n\r\n/a");
+        assert!(keep_alive);
+
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.1\r\nConnection: Close\r\n\r\nGET /b HTTP/1.1\r\n\r\n", 100, &echo_path);
+        assert!(response_text.contains("connection: close\r\n"));
+        assert!(!response_text.contains("/b"));
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn keeps_http_10_connections_alive_only_when_asked() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.0\r\n\r\n", 100, &echo_path);
+        assert!(response_text.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(!response_text.contains("connection:"));
+        assert!(!keep_alive);
+
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\n", 100, &echo_path);
+        assert!(response_text.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response_text.contains("connection: keep-alive\r\n"));
+        assert!(keep_alive);
+    }
+
+    #[test]
+    fn closes_after_max_requests() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n\r\n", 2, &echo_path);
+        assert_eq!(response_text.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(response_text.ends_with("connection: close\r\ncontent-length: 2\r\n\r\n/b"));
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn closes_after_malformed_request() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.1\r\nBad header\r\n\r\nGET /b HTTP/1.1\r\n\r\n", 100, &echo_path);
+        assert!(response_text.contains(" 400 Bad Request\r\n"));
+        assert!(!response_text.contains("/b"));
+        assert!(!keep_alive);
+    }
+
+    #[test]
+    fn answers_pipelined_requests_in_order() {
+        let (response_text, keep_alive) = serve_fixture(b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 3\r\n\r\nxyzGET /c HTTP/1.1\r\n\r\n", 100, &echo_path);
+        let response_bodies: Vec<&str> = response_text.split("\r\n\r\n").skip(1).map(|after_headers| &after_headers[..2]).collect();
+        assert_eq!(response_bodies, ["/a", "/b", "/c"]);
+        assert!(keep_alive);
+    }
+
+    #[test]
+    fn writes_head_responses_without_body() {
+        let router = crate::Router::new()
+            .get("/page", echo_path)
+            .route(http::Method::HEAD, "/sized", |_request| {
+                let mut response = crate::create_bytes_response(http::StatusCode::OK, http::HeaderValue::from_static("application/octet-stream"), b"");
+                response.headers_mut().insert(http::header::CONTENT_LENGTH, http::HeaderValue::from_static("1234"));
+                response
+            });
+
+        let (response_text, keep_alive) = serve_fixture(b"HEAD /page HTTP/1.1\r\n\r\nHEAD /sized HTTP/1.1\r\n\r\nGET /page HTTP/1.1\r\n\r\n", 100, &router);
+        assert_eq!(response_text, concat!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-type: application/octet-stream\r\ncontent-length: 1234\r\n\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\n/page",
+        ));
+        assert!(keep_alive);
+    }
+
+    #[test]
+    fn stops_from_server_handle() {
+        let server = super::Server::builder()
+            .bind("127.0.0.1:0")
+            .handler(echo_path)
+            .build()
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        assert_ne!(server_addr.port(), 0);
+        let server_handle = server.handle();
+        let server_thread = std::thread::spawn(move || server.run());
+
+        assert!(send_over_tcp(server_addr, b"GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n").ends_with("/hello"));
+        assert!(!server_handle.is_stopping());
+
+        server_handle.stop();
+        assert!(server_handle.is_stopping());
+        server_thread.join().unwrap().unwrap();
+        assert!(std::net::TcpStream::connect(server_addr).is_err());
+    }
+
+    #[test]
+    fn stops_from_server_control() {
+        let server = super::Server::builder()
+            .bind("127.0.0.1:0")
+            .handler(|_request| {
+                let mut response = crate::create_text_response(http::StatusCode::OK, "stopping");
+                response.extensions_mut().insert(super::ServerControl { should_stop: true });
+                response
+            })
+            .build()
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let server_thread = std::thread::spawn(move || server.run());
+
+        let response_text = send_over_tcp(server_addr, b"GET /stop HTTP/1.1\r\n\r\n");
+        assert!(response_text.contains("connection: close\r\n"));
+        assert!(response_text.ends_with("stopping"));
+        server_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn rejects_connections_when_queue_is_full() {
+        let server = super::Server::builder()
+            .bind("127.0.0.1:0")
+            .handler(echo_path)
+            .workers(1)
+            .queue_depth(1)
+            .shutdown_grace_period(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let server_handle = server.handle();
+        let server_thread = std::thread::spawn(move || server.run());
+
+        // The only worker is kept busy by a half-sent request, and the one queue slot by a silent connection.
+        let mut busy_stream = std::net::TcpStream::connect(server_addr).unwrap();
+        std::io::Write::write_all(&mut busy_stream, b"GET / HTTP/1.1\r\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let queued_stream = std::net::TcpStream::connect(server_addr).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let response_text = send_over_tcp(server_addr, b"GET / HTTP/1.1\r\n\r\n");
+        assert!(response_text.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response_text.contains("retry-after: 1\r\n"));
+        assert!(response_text.contains("connection: close\r\n"));
+
+        server_handle.stop();
+        server_thread.join().unwrap().unwrap();
+        drop(queued_stream);
+    }
+}
@@ -0,0 +1,336 @@
+/// Serves files from a directory, e.g. `Router::new().get("/assets/*path", StaticFiles::new("public"))`.
+///
+/// Under a route ending in a wildcard, the path the wildcard captured is looked up in the directory;
+/// otherwise (e.g. as the server's whole handler) the request's full path is.
+/// A directory is served by its index file, after redirecting to add a trailing '/' if the request didn't have one.
+/// If there's no index file, the directory is 404 unless listings are turned on with `listing(true)`.
+///
+/// Paths with ".." segments are rejected with 403, and paths that resolve outside the directory (e.g. through a symlink) with 404.
+///
+/// Each file is read into memory whole before it's sent, so files larger than `max_file_size()` (64 MiB by default)
+/// aren't served, and get a 500 instead.
+pub struct StaticFiles {
+    root: std::path::PathBuf,
+    index_file: String,
+    listing: bool,
+    max_file_size: u64,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        StaticFiles {
+            root: root.into(),
+            index_file: "index.html".to_string(),
+            listing: false,
+            max_file_size: 64 * 1024 * 1024,
+        }
+    }
+
+    /// The file that's served for a directory, "index.html" by default.
+    pub fn index_file(mut self, index_file: &str) -> Self {
+        self.index_file = index_file.to_string();
+        self
+    }
+
+    /// Whether a directory without an index file gets a page listing its entries, as HTML or JSON; off by default.
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    /// The largest file that's served, in bytes; see above.
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    fn read_file(&self, file_path: &std::path::Path) -> Result<Vec<u8>, std::io::Error> {
+        let file = std::fs::File::open(file_path)?;
+        // The size is checked up-front, and the read is capped too, in case the file grows in the meantime.
+        let file_size = file.metadata()?.len();
+        if file_size > self.max_file_size {
+            return Err(std::io::Error::new(std::io::ErrorKind::FileTooLarge, format!("{} is {} bytes, more than the maximum {}", file_path.display(), file_size, self.max_file_size)));
+        }
+
+        let mut file_bytes = Vec::with_capacity(file_size as usize);
+        std::io::Read::read_to_end(&mut std::io::Read::take(file, self.max_file_size + 1), &mut file_bytes)?;
+        if file_bytes.len() as u64 > self.max_file_size {
+            return Err(std::io::Error::new(std::io::ErrorKind::FileTooLarge, format!("{} grew past the maximum {} bytes", file_path.display(), self.max_file_size)));
+        }
+
+        Ok(file_bytes)
+    }
+
+    fn serve(&self, request: &crate::Request) -> Result<crate::Response, http::StatusCode> {
+        let relative_path = match request.extensions().get::<crate::PathParams>().and_then(|path_params| path_params.rest()) {
+            Some(rest) => rest.to_string(),
+            None => crate::router::percent_decode(request.uri().path()),
+        };
+        let file_path = resolve_file_path(&self.root, &relative_path)?;
+
+        let metadata = std::fs::metadata(&file_path).map_err(|metadata_error| io_error_status(&metadata_error))?;
+        if metadata.is_dir() {
+            // Relative links in an index page only work when the directory's URL ends in '/'.
+            if !request.uri().path().ends_with('/') {
+                return Ok(create_directory_redirect(request.uri()));
+            }
+
+            let index_path = file_path.join(&self.index_file);
+            return match self.read_file(&index_path) {
+                Ok(index_bytes) => Ok(create_file_response(&index_path, &index_bytes)),
+                Err(read_error) if read_error.kind() == std::io::ErrorKind::NotFound && self.listing => {
+                    let has_parent = !normalize_path(&relative_path)?.is_empty();
+                    crate::directory_listing::create_listing_response(request, &file_path, has_parent)
+                        .map_err(|listing_error| io_error_status(&listing_error))
+                },
+                Err(read_error) => Err(io_error_status(&read_error))
+            };
+        }
+
+        match self.read_file(&file_path) {
+            Ok(file_bytes) => Ok(create_file_response(&file_path, &file_bytes)),
+            Err(read_error) => Err(io_error_status(&read_error))
+        }
+    }
+}
+
+impl crate::Handler for StaticFiles {
+    fn call(&self, request: crate::Request) -> crate::Response {
+        if request.method() != http::Method::GET && request.method() != http::Method::HEAD {
+            let mut response = crate::create_text_response(http::StatusCode::METHOD_NOT_ALLOWED, &format!("Method {} not allowed for static files", request.method()));
+            response.headers_mut().insert(http::header::ALLOW, http::HeaderValue::from_static("GET, HEAD"));
+            return response;
+        }
+
+        match self.serve(&request) {
+            Ok(response) => response,
+            Err(status) => {
+                let message = match status {
+                    http::StatusCode::FORBIDDEN => format!("Forbidden path {}", request.uri().path()),
+                    http::StatusCode::NOT_FOUND => format!("Unrecognized path {}", request.uri().path()),
+                    _ => format!("Couldn't read path {}", request.uri().path()),
+                };
+                crate::create_text_response(status, &message)
+            }
+        }
+    }
+}
+
+/// The segments of a (percent-decoded) request path, without empty or "." segments,
+/// or 403 if any segment could reach outside the directory it's relative to.
+pub(crate) fn normalize_path(relative_path: &str) -> Result<Vec<&str>, http::StatusCode> {
+    let mut segments = Vec::new();
+    for segment in relative_path.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+
+        // Backslashes would be separators on Windows, and a NUL can't be in a file name at all.
+        if segment == ".." || segment.contains('\\') || segment.contains('\0') {
+            return Err(http::StatusCode::FORBIDDEN);
+        }
+
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+/// The path of a file under the root directory, with any symlinks resolved,
+/// or 404 if there's no such file or it's actually outside of the root.
+pub(crate) fn resolve_file_path(root: &std::path::Path, relative_path: &str) -> Result<std::path::PathBuf, http::StatusCode> {
+    let segments = normalize_path(relative_path)?;
+
+    let root_path = root.canonicalize().map_err(|root_error| {
+        log::error!("Static file root {} error: {}", root.display(), root_error);
+        http::StatusCode::NOT_FOUND
+    })?;
+
+    let mut file_path = root_path.clone();
+    file_path.extend(segments);
+    let file_path = file_path.canonicalize().map_err(|_| http::StatusCode::NOT_FOUND)?;
+
+    if !file_path.starts_with(&root_path) {
+        log::warn!("Static file path {} is outside of root {}", file_path.display(), root_path.display());
+        return Err(http::StatusCode::NOT_FOUND);
+    }
+
+    Ok(file_path)
+}
+
+fn io_error_status(io_error: &std::io::Error) -> http::StatusCode {
+    match io_error.kind() {
+        std::io::ErrorKind::NotFound => http::StatusCode::NOT_FOUND,
+        std::io::ErrorKind::PermissionDenied => http::StatusCode::FORBIDDEN,
+        _ => {
+            log::error!("Static file error: {}", io_error);
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn create_directory_redirect(uri: &http::Uri) -> crate::Response {
+    let location = match uri.query() {
+        Some(query) => format!("{}/?{}", uri.path(), query),
+        None => format!("{}/", uri.path()),
+    };
+
+    let mut response = crate::create_text_response(http::StatusCode::MOVED_PERMANENTLY, &format!("Moved to {}", location));
+    // The location is built from a path that was already a valid URI, so it's a valid header value too.
+    if let Ok(location_value) = http::HeaderValue::from_str(&location) {
+        response.headers_mut().insert(http::header::LOCATION, location_value);
+    }
+    response
+}
+
+fn create_file_response(file_path: &std::path::Path, file_bytes: &[u8]) -> crate::Response {
+    let content_type = http::HeaderValue::from_static(content_type_for(file_path));
+    crate::create_bytes_response(http::StatusCode::OK, content_type, file_bytes)
+}
+
+/// The media type for a file, going by its extension.
+pub(crate) fn content_type_for(file_path: &std::path::Path) -> &'static str {
+    let extension = file_path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // A served directory, with a sibling that's outside of it, under a temporary directory of the test's own.
+    fn make_site(test_name: &str) -> std::path::PathBuf {
+        let site_dir = std::env::temp_dir().join(format!("lrn2rust-static-{}-{}", std::process::id(), test_name));
+        let _ = std::fs::remove_dir_all(&site_dir);
+        std::fs::create_dir_all(site_dir.join("root/docs")).unwrap();
+        std::fs::create_dir_all(site_dir.join("outside")).unwrap();
+        std::fs::write(site_dir.join("root/docs/index.html"), "<h1>docs</h1>").unwrap();
+        std::fs::write(site_dir.join("root/notes.txt"), "notes").unwrap();
+        std::fs::write(site_dir.join("root/style.css"), "body {}").unwrap();
+        std::fs::write(site_dir.join("outside/secret.txt"), "secret").unwrap();
+        site_dir
+    }
+
+    fn get(static_files: &super::StaticFiles, uri: &str) -> crate::Response {
+        crate::test_support::call(static_files, http::Method::GET, uri)
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let site_dir = make_site("traversal");
+        let router = crate::Router::new().get("/files/*path", super::StaticFiles::new(site_dir.join("root")));
+        let static_files = super::StaticFiles::new(site_dir.join("root"));
+
+        for uri in ["/files/../outside/secret.txt", "/files/%2e%2e/outside/secret.txt", "/files/docs%2F..%2F..%2Foutside%2Fsecret.txt", "/files/..%5Coutside%5Csecret.txt", "/files/notes.txt%00"] {
+            assert_eq!(crate::test_support::call(&router, http::Method::GET, uri).status(), http::StatusCode::FORBIDDEN, "{}", uri);
+        }
+        for uri in ["/../outside/secret.txt", "/%2E%2E/outside/secret.txt", "/docs/%2e%2e/%2e%2e/outside/secret.txt"] {
+            assert_eq!(get(&static_files, uri).status(), http::StatusCode::FORBIDDEN, "{}", uri);
+        }
+
+        let _ = std::fs::remove_dir_all(&site_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hides_symlinks_out_of_root() {
+        let site_dir = make_site("symlink");
+        std::os::unix::fs::symlink(site_dir.join("outside"), site_dir.join("root/escape")).unwrap();
+        let static_files = super::StaticFiles::new(site_dir.join("root"));
+
+        assert_eq!(get(&static_files, "/escape/secret.txt").status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(get(&static_files, "/notes.txt").status(), http::StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&site_dir);
+    }
+
+    #[test]
+    fn serves_directories_by_index_file() {
+        let site_dir = make_site("index");
+        let static_files = super::StaticFiles::new(site_dir.join("root"));
+
+        let response = get(&static_files, "/docs/");
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers()[http::header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(crate::test_support::body_text(&response), "<h1>docs</h1>");
+
+        // Relative links only work from a URL ending in '/', so one is added first, keeping the query.
+        let response = get(&static_files, "/docs?lang=en");
+        assert_eq!(response.status(), http::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[http::header::LOCATION], "/docs/?lang=en");
+
+        // Without an index file or listings, a directory isn't served.
+        assert_eq!(get(&static_files, "/").status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(get(&super::StaticFiles::new(site_dir.join("root")).index_file("missing.html"), "/docs/").status(), http::StatusCode::NOT_FOUND);
+
+        let _ = std::fs::remove_dir_all(&site_dir);
+    }
+
+    #[test]
+    fn enforces_max_file_size() {
+        let site_dir = make_site("max-size");
+        let static_files = super::StaticFiles::new(site_dir.join("root")).max_file_size(5);
+
+        assert_eq!(crate::test_support::body_text(&get(&static_files, "/notes.txt")), "notes");
+        assert_eq!(get(&static_files, "/style.css").status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(get(&static_files, "/docs/").status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        let _ = std::fs::remove_dir_all(&site_dir);
+    }
+
+    #[test]
+    fn only_answers_get_and_head() {
+        let site_dir = make_site("methods");
+        let static_files = super::StaticFiles::new(site_dir.join("root"));
+
+        assert_eq!(crate::test_support::call(&static_files, http::Method::HEAD, "/notes.txt").status(), http::StatusCode::OK);
+        let response = crate::test_support::call(&static_files, http::Method::POST, "/notes.txt");
+        assert_eq!(response.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[http::header::ALLOW], "GET, HEAD");
+
+        let _ = std::fs::remove_dir_all(&site_dir);
+    }
+
+    #[test]
+    fn maps_extensions_to_content_types() {
+        assert_eq!(super::content_type_for(std::path::Path::new("notes.txt")), "text/plain; charset=utf-8");
+        assert_eq!(super::content_type_for(std::path::Path::new("site.CSS")), "text/css; charset=utf-8");
+        assert_eq!(super::content_type_for(std::path::Path::new("app.mjs")), "text/javascript; charset=utf-8");
+        assert_eq!(super::content_type_for(std::path::Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(super::content_type_for(std::path::Path::new("photo.JPEG")), "image/jpeg");
+        assert_eq!(super::content_type_for(std::path::Path::new("archive.tar.gz")), "application/gzip");
+        assert_eq!(super::content_type_for(std::path::Path::new("README")), "application/octet-stream");
+        assert_eq!(super::content_type_for(std::path::Path::new("data.unknown")), "application/octet-stream");
+    }
+}
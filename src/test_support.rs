@@ -0,0 +1,13 @@
+// Helpers shared by the in-module tests.
+
+pub(crate) fn request(method: http::Method, uri: &str) -> crate::Request {
+    http::Request::builder().method(method).uri(uri).body(Vec::new()).unwrap()
+}
+
+pub(crate) fn call(handler: &dyn crate::Handler, method: http::Method, uri: &str) -> crate::Response {
+    handler.call(request(method, uri))
+}
+
+pub(crate) fn body_text(response: &crate::Response) -> &str {
+    std::str::from_utf8(response.body()).unwrap()
+}